@@ -0,0 +1,322 @@
+// The camera used to zoom and pan around the grid.
+// vs_main places the grid in "world" space, where it spans -1..1 on both axes. The camera maps
+// world space to clip space with: clip = (world - centre) * zoom * viewport_scale
+// where viewport_scale keeps the cells square for the current window and grid sizes.
+
+const MIN_ZOOM: f32 = 0.5;
+// How many cells should still fit across the window when fully zoomed in
+const MIN_VISIBLE_CELLS: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AspectMode {
+    // Show the whole grid, with empty bars along the sides that don't fit
+    Letterbox,
+    // Fill the window with the grid, cropping whichever axis doesn't fit
+    Fill,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ViewUniform {
+    scale: [f32; 2],
+    offset: [f32; 2],
+}
+
+pub(crate) struct Camera {
+    zoom: f32,
+    centre: [f32; 2],
+    viewport_scale: [f32; 2],
+    aspect_mode: AspectMode,
+    grid_size: (u32, u32),
+}
+
+impl Camera {
+    // A camera showing the whole grid
+    pub(crate) fn new(window_size: (u32, u32), grid_size: (u32, u32)) -> Self {
+        let mut camera = Self {
+            zoom: 1.0,
+            centre: [0.0, 0.0],
+            viewport_scale: [1.0, 1.0],
+            aspect_mode: AspectMode::Letterbox,
+            grid_size,
+        };
+        camera.set_viewport(window_size, grid_size);
+        camera
+    }
+
+    // Reset the zoom and pan to show the whole grid
+    pub(crate) fn fit(&mut self) {
+        self.zoom = 1.0;
+        self.centre = [0.0, 0.0];
+    }
+
+    pub(crate) fn aspect_mode(&self) -> AspectMode {
+        self.aspect_mode
+    }
+
+    pub(crate) fn set_aspect_mode(&mut self, window_size: (u32, u32), aspect_mode: AspectMode) {
+        self.aspect_mode = aspect_mode;
+        self.set_viewport(window_size, self.grid_size);
+    }
+
+    // Update the viewport scale after the window or grid has been resized.
+    // A cell is c pixels wide on both axes, where c is picked so that the grid fits inside
+    // (letterbox) or covers (fill) the window. The viewport scale then maps the grid's -1..1
+    // world space onto the right fraction of the window's -1..1 clip space.
+    pub(crate) fn set_viewport(&mut self, window_size: (u32, u32), grid_size: (u32, u32)) {
+        self.grid_size = grid_size;
+
+        let pixels_per_cell_x = window_size.0 as f32 / grid_size.0 as f32;
+        let pixels_per_cell_y = window_size.1 as f32 / grid_size.1 as f32;
+        let cell_pixels = match self.aspect_mode {
+            AspectMode::Letterbox => pixels_per_cell_x.min(pixels_per_cell_y),
+            AspectMode::Fill => pixels_per_cell_x.max(pixels_per_cell_y),
+        };
+
+        self.viewport_scale = [
+            cell_pixels / pixels_per_cell_x,
+            cell_pixels / pixels_per_cell_y,
+        ];
+    }
+
+    pub(crate) fn view_uniform(&self) -> ViewUniform {
+        let scale = self.scale();
+        ViewUniform {
+            scale,
+            offset: [-self.centre[0] * scale[0], -self.centre[1] * scale[1]],
+        }
+    }
+
+    pub(crate) fn clip_to_world(&self, clip: [f32; 2]) -> [f32; 2] {
+        let scale = self.scale();
+        [
+            clip[0] / scale[0] + self.centre[0],
+            clip[1] / scale[1] + self.centre[1],
+        ]
+    }
+
+    // Zoom by a factor, keeping the point under the anchor (in clip space) fixed
+    pub(crate) fn zoom_at(&mut self, factor: f32, anchor: [f32; 2]) {
+        let anchor_world = self.clip_to_world(anchor);
+
+        let max_zoom = (self.grid_size.0.max(self.grid_size.1) as f32 / MIN_VISIBLE_CELLS).max(1.0);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, max_zoom);
+
+        let scale = self.scale();
+        self.centre = [
+            anchor_world[0] - anchor[0] / scale[0],
+            anchor_world[1] - anchor[1] / scale[1],
+        ];
+        self.clamp_centre();
+    }
+
+    // Move the view so that the grid moves by a distance in clip space
+    pub(crate) fn pan(&mut self, clip_delta: [f32; 2]) {
+        let scale = self.scale();
+        self.centre[0] -= clip_delta[0] / scale[0];
+        self.centre[1] -= clip_delta[1] / scale[1];
+        self.clamp_centre();
+    }
+
+    fn scale(&self) -> [f32; 2] {
+        [
+            self.zoom * self.viewport_scale[0],
+            self.zoom * self.viewport_scale[1],
+        ]
+    }
+
+    // Keep the centre of the view over the grid so it can't be lost off screen
+    fn clamp_centre(&mut self) {
+        self.centre[0] = self.centre[0].clamp(-1.0, 1.0);
+        self.centre[1] = self.centre[1].clamp(-1.0, 1.0);
+    }
+}
+
+// The camera used to look around a 3D grid. It orbits the centre of the grid, which vs_cube
+// places in world space so that the grid fits inside -1..1 on every axis, with y up.
+// Angles are in radians: yaw turns the camera around the y axis, starting from in front of the
+// grid (+z), and pitch raises it above the grid.
+
+const DEFAULT_YAW: f32 = 0.6;
+const DEFAULT_PITCH: f32 = 0.4;
+// Far enough for the whole grid to fit in view
+const DEFAULT_DISTANCE: f32 = 4.5;
+const MIN_DISTANCE: f32 = 0.5;
+const MAX_DISTANCE: f32 = 20.0;
+// Just short of straight up or down, where the camera's up direction would be undefined
+const MAX_PITCH: f32 = 1.5;
+const FIELD_OF_VIEW: f32 = std::f32::consts::FRAC_PI_4;
+const NEAR_PLANE: f32 = 0.01;
+const FAR_PLANE: f32 = 100.0;
+
+// A column-major matrix, as WGSL's mat4x4 is laid out
+type Matrix = [[f32; 4]; 4];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct OrbitUniform {
+    view_projection: Matrix,
+}
+
+pub(crate) struct OrbitCamera {
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+impl OrbitCamera {
+    // A camera looking down at the grid from in front and to the right
+    pub(crate) fn new() -> Self {
+        Self {
+            yaw: DEFAULT_YAW,
+            pitch: DEFAULT_PITCH,
+            distance: DEFAULT_DISTANCE,
+        }
+    }
+
+    // Reset the camera to its starting position
+    pub(crate) fn fit(&mut self) {
+        *self = Self::new();
+    }
+
+    // Turn the camera around the grid by angles in radians
+    pub(crate) fn orbit(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = (self.yaw + yaw) % std::f32::consts::TAU;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    // Move towards the grid by a factor, e.g. 2 halves the distance
+    pub(crate) fn zoom(&mut self, factor: f32) {
+        self.distance = (self.distance / factor).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+
+    // The transform from world space to clip space, for a window of the given width / height
+    pub(crate) fn orbit_uniform(&self, aspect_ratio: f32) -> OrbitUniform {
+        let eye = [
+            self.distance * self.pitch.cos() * self.yaw.sin(),
+            self.distance * self.pitch.sin(),
+            self.distance * self.pitch.cos() * self.yaw.cos(),
+        ];
+        OrbitUniform {
+            view_projection: multiply(&perspective(aspect_ratio), &look_at_origin(eye)),
+        }
+    }
+}
+
+// A right-handed perspective projection onto wgpu's clip space, where depth runs from 0 at the
+// near plane to 1 at the far plane
+fn perspective(aspect_ratio: f32) -> Matrix {
+    let focal_length = 1.0 / (FIELD_OF_VIEW / 2.0).tan();
+    let depth_scale = FAR_PLANE / (NEAR_PLANE - FAR_PLANE);
+    [
+        [focal_length / aspect_ratio, 0.0, 0.0, 0.0],
+        [0.0, focal_length, 0.0, 0.0],
+        [0.0, 0.0, depth_scale, -1.0],
+        [0.0, 0.0, NEAR_PLANE * depth_scale, 0.0],
+    ]
+}
+
+// The view transform of a camera at eye looking at the origin, with y up
+fn look_at_origin(eye: [f32; 3]) -> Matrix {
+    let forward = normalise([-eye[0], -eye[1], -eye[2]]);
+    let right = normalise(cross(forward, [0.0, 1.0, 0.0]));
+    let up = cross(right, forward);
+    [
+        [right[0], up[0], -forward[0], 0.0],
+        [right[1], up[1], -forward[1], 0.0],
+        [right[2], up[2], -forward[2], 0.0],
+        [-dot(right, eye), -dot(up, eye), dot(forward, eye), 1.0],
+    ]
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [[0.0; 4]; 4];
+    for (column, b_column) in product.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    product
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalise(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    [v[0] / length, v[1] / length, v[2] / length]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 2], expected: [f32; 2]) {
+        let close = (0..2).all(|i| (actual[i] - expected[i]).abs() < 1e-5);
+        assert!(close, "{actual:?} isn't close to {expected:?}");
+    }
+
+    // Where a point in world space is drawn in clip space
+    fn world_to_clip(camera: &Camera, world: [f32; 2]) -> [f32; 2] {
+        let view = camera.view_uniform();
+        [
+            world[0] * view.scale[0] + view.offset[0],
+            world[1] * view.scale[1] + view.offset[1],
+        ]
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_anchor() {
+        let mut camera = Camera::new((800, 600), (100, 100));
+        for (factor, anchor) in [(2.0, [0.3, -0.5]), (1.5, [-0.8, 0.2]), (0.5, [0.1, 0.9])] {
+            let before = camera.clip_to_world(anchor);
+            camera.zoom_at(factor, anchor);
+            assert_close(camera.clip_to_world(anchor), before);
+        }
+    }
+
+    #[test]
+    fn clip_to_world_inverts_the_view() {
+        let mut camera = Camera::new((800, 600), (100, 100));
+        camera.zoom_at(3.0, [0.4, 0.6]);
+        camera.pan([-0.2, 0.1]);
+        for world in [[0.0, 0.0], [1.0, -1.0], [-0.3, 0.7]] {
+            assert_close(camera.clip_to_world(world_to_clip(&camera, world)), world);
+        }
+    }
+
+    #[test]
+    fn cells_stay_square_in_wide_and_tall_windows() {
+        let windows = [(1600, 600), (600, 1600)];
+        let grids = [(100, 50), (50, 100), (64, 64)];
+        for aspect_mode in [AspectMode::Letterbox, AspectMode::Fill] {
+            for (window, grid) in windows.into_iter().flat_map(|w| grids.map(|g| (w, g))) {
+                let mut camera = Camera::new(window, grid);
+                camera.set_aspect_mode(window, aspect_mode);
+                let scale = camera.view_uniform().scale;
+
+                // The grid spans 2 in world space, and the window 2 in clip space
+                let cell_width = scale[0] * window.0 as f32 / grid.0 as f32;
+                let cell_height = scale[1] * window.1 as f32 / grid.1 as f32;
+                let case = format!("{aspect_mode:?} {window:?} {grid:?}");
+                assert!((cell_width - cell_height).abs() < 1e-4, "{case}");
+
+                // The grid fits the window along one axis, and fits inside or covers it along
+                // the other
+                match aspect_mode {
+                    AspectMode::Letterbox => assert_eq!(scale[0].max(scale[1]), 1.0, "{case}"),
+                    AspectMode::Fill => assert_eq!(scale[0].min(scale[1]), 1.0, "{case}"),
+                }
+            }
+        }
+    }
+}
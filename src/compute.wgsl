@@ -0,0 +1,536 @@
+// The grid's width, height and depth. Only 3D rules have more than one layer.
+@group(0) @binding(0) var<uniform> grid: vec3<f32>;
+@group(0) @binding(1) var<storage> cell_state_in: array<u32>;
+@group(0) @binding(2) var<storage, read_write> cell_state_out: array<u32>;
+@group(0) @binding(3) var<uniform> rule: Rule;
+@group(0) @binding(4) var<storage> cell_age_in: array<u32>;
+@group(0) @binding(5) var<storage, read_write> cell_age_out: array<u32>;
+@group(0) @binding(6) var<uniform> topology: Topology;
+@group(0) @binding(7) var<storage> rule_table: array<u32>;
+// Continuous rules only: each cell's value, and the weights of the Lenia kernel
+@group(0) @binding(8) var<storage> cell_value_in: array<f32>;
+@group(0) @binding(9) var<storage, read_write> cell_value_out: array<f32>;
+@group(0) @binding(10) var<storage> lenia_kernel: array<f32>;
+// One dimensional rules only: where the current generation is in the ring of rows
+@group(0) @binding(11) var<uniform> history: History;
+
+// Life-like or Generations rule in B/S/C notation, Wireworld, a Larger than Life rule, or a 3D
+// rule, which uses the ranges of counts too. Each kind of rule has its own entry point, so the
+// kind is only needed when rendering.
+// Life-like and Generations rules are compiled to rule_table, which has an entry for each
+// arrangement of active neighbours: bit 0 is set if a dead cell is born, and bit 1 if a live
+// cell survives. Cells that don't survive pass through the dying states 2..states-1 before dying.
+struct Rule {
+    kind: u32,
+    states: u32,
+    lattice: u32,
+    // Larger than Life rules count the neighbours within the radius, optionally including the
+    // cell itself, and use inclusive ranges of counts
+    radius: u32,
+    neighbourhood: u32,
+    middle: u32,
+    // Lenia rules use the radius too, for the size of the kernel. Each generation adds
+    // growth(potential) * time_step to a cell's value.
+    growth: u32,
+    // 3D rules use masks with a bit for each count a cell is born / survives with
+    birth_counts: u32,
+    birth_range: vec2<u32>,
+    survive_range: vec2<u32>,
+    growth_centre: f32,
+    growth_width: f32,
+    time_step: f32,
+    survive_counts: u32,
+};
+
+// Must match RuleUniform in rule.rs
+const NEIGHBOURHOOD_MOORE: u32 = 0u;
+const NEIGHBOURHOOD_VON_NEUMANN: u32 = 1u;
+const NEIGHBOURHOOD_HEXAGONAL: u32 = 2u;
+const NEIGHBOURHOOD_TRIANGULAR: u32 = 3u;
+const NEIGHBOURHOOD_TRIANGULAR_EDGES: u32 = 4u;
+const GROWTH_POLYNOMIAL: u32 = 1u;
+const GROWTH_EXPONENTIAL: u32 = 2u;
+const GROWTH_STEP: u32 = 3u;
+
+// Must match the WIREWORLD_ constants in rule.rs
+const WIREWORLD_EMPTY: u32 = 0u;
+const WIREWORLD_HEAD: u32 = 1u;
+const WIREWORLD_TAIL: u32 = 2u;
+const WIREWORLD_CONDUCTOR: u32 = 3u;
+
+// One dimensional rules use the grid as a ring of rows, each holding a generation. Must match
+// HistoryUniform in simulation.rs.
+struct History {
+    // The row holding the current generation. The next generation goes in the row after it,
+    // wrapping around to replace the oldest.
+    row: u32,
+};
+
+// How the edges of the grid join up
+struct Topology {
+    kind: u32,
+};
+
+// Must match TopologyUniform in topology.rs
+const TOPOLOGY_BOUNDED: u32 = 0u;
+const TOPOLOGY_TORUS: u32 = 1u;
+const TOPOLOGY_KLEIN_BOTTLE: u32 = 2u;
+const TOPOLOGY_CROSS_SURFACE: u32 = 3u;
+const TOPOLOGY_CYLINDER: u32 = 4u;
+
+// Compute shader
+
+// How many times the grid's edge is crossed to get from 0 to n, rounding towards negative
+// infinity like i64::div_euclid
+fn wrap_count(n: i32, size: i32) -> i32 {
+    return select(n / size, (n - size + 1) / size, n < 0);
+}
+
+// The index of the neighbour at (x, y), or -1 if there's no cell there.
+// The neighbour may be outside the grid, see Topology::wrap in topology.rs.
+fn neighbour_index(x: i32, y: i32) -> i32 {
+    let size = vec2<i32>(grid.xy);
+    let wraps = vec2<i32>(wrap_count(x, size.x), wrap_count(y, size.y));
+    let outside_x = wraps.x != 0;
+    let outside_y = wraps.y != 0;
+    // Crossing an edge an even number of times undoes any mirroring
+    let mirror_x = wraps.x % 2 != 0;
+    let mirror_y = wraps.y % 2 != 0;
+    var neighbour = vec2<i32>(x, y);
+
+    switch topology.kind {
+        case TOPOLOGY_BOUNDED: {
+            if (outside_x || outside_y) {
+                return -1;
+            }
+        }
+        case TOPOLOGY_CYLINDER: {
+            if (outside_y) {
+                return -1;
+            }
+        }
+        case TOPOLOGY_KLEIN_BOTTLE: {
+            // Crossing the top or bottom edge mirrors x
+            if (mirror_y) {
+                neighbour.x = size.x - 1 - neighbour.x;
+            }
+        }
+        case TOPOLOGY_CROSS_SURFACE: {
+            // Crossing any edge mirrors the other axis
+            if (mirror_x) {
+                neighbour.y = size.y - 1 - neighbour.y;
+            }
+            if (mirror_y) {
+                neighbour.x = size.x - 1 - neighbour.x;
+            }
+        }
+        default: {}
+    }
+
+    // Wrap around
+    let wrapped = neighbour - size * vec2<i32>(
+        wrap_count(neighbour.x, size.x),
+        wrap_count(neighbour.y, size.y),
+    );
+    return wrapped.y * size.x + wrapped.x;
+}
+
+// Whether the neighbour at (x, y) is alive (1) or not (0). Dying cells don't count.
+// In Wireworld, electron heads are the active cells.
+fn neighbour_active(x: i32, y: i32) -> u32 {
+    let i = neighbour_index(x, y);
+    return select(0u, 1u, i >= 0 && cell_state_in[i] == 1u);
+}
+
+// How many of the 8 cells around (x, y) are active
+fn count_active_neighbours(x: i32, y: i32) -> u32 {
+    // Neighbour coordinates, which are signed as they may fall outside the grid
+    let left = x - 1;
+    let right = x + 1;
+    let down = y - 1;
+    let up = y + 1;
+
+    return neighbour_active(right, up) +
+           neighbour_active(right, y) +
+           neighbour_active(right, down) +
+           neighbour_active(x, down) +
+           neighbour_active(left, down) +
+           neighbour_active(left, y) +
+           neighbour_active(left, up) +
+           neighbour_active(x, up);
+}
+
+// Which of the 8 cells around (x, y) are active, with a bit for each in reading order: NW, N, NE,
+// W, E, SW, S, SE, where north is +y. Must match the neighbourhoods in hensel.rs.
+fn active_neighbourhood(x: i32, y: i32) -> u32 {
+    let left = x - 1;
+    let right = x + 1;
+    let down = y - 1;
+    let up = y + 1;
+
+    return neighbour_active(left, up) |
+           neighbour_active(x, up) << 1u |
+           neighbour_active(right, up) << 2u |
+           neighbour_active(left, y) << 3u |
+           neighbour_active(right, y) << 4u |
+           neighbour_active(left, down) << 5u |
+           neighbour_active(x, down) << 6u |
+           neighbour_active(right, down) << 7u;
+}
+
+// The same for the 6 cells around (x, y) on the hexagonal lattice, in reading order. Odd rows
+// are shifted right by half a cell, so a cell's neighbours above and below are the two cells
+// either side of its centre.
+fn active_hexagonal_neighbourhood(x: i32, y: i32) -> u32 {
+    let shift = y & 1;
+    let left = x - 1 + shift;
+    let right = x + shift;
+    let down = y - 1;
+    let up = y + 1;
+
+    return neighbour_active(left, up) |
+           neighbour_active(right, up) << 1u |
+           neighbour_active(x - 1, y) << 2u |
+           neighbour_active(x + 1, y) << 3u |
+           neighbour_active(left, down) << 4u |
+           neighbour_active(right, down) << 5u;
+}
+
+// The row that a triangle at (x, y) shares its base with: the row below if it points up, or the
+// row above if it points down
+fn triangle_base_row(x: i32, y: i32) -> i32 {
+    return select(y + 1, y - 1, ((x + y) & 1) == 0);
+}
+
+// The same for the 12 cells sharing a corner with (x, y) on the triangular lattice: the 5 cells
+// in the row its base touches, the 4 either side of it, then the 3 in the row its tip touches
+fn active_triangular_neighbourhood(x: i32, y: i32) -> u32 {
+    let base_row = triangle_base_row(x, y);
+    let tip_row = 2 * y - base_row;
+
+    var neighbourhood = 0u;
+    var bit = 0u;
+    for (var dx = -2; dx <= 2; dx++) {
+        neighbourhood |= neighbour_active(x + dx, base_row) << bit;
+        bit++;
+    }
+    for (var dx = -2; dx <= 2; dx++) {
+        if (dx != 0) {
+            neighbourhood |= neighbour_active(x + dx, y) << bit;
+            bit++;
+        }
+    }
+    for (var dx = -1; dx <= 1; dx++) {
+        neighbourhood |= neighbour_active(x + dx, tip_row) << bit;
+        bit++;
+    }
+    return neighbourhood;
+}
+
+// The same for the 3 cells sharing an edge with (x, y) on the triangular lattice
+fn active_triangular_edge_neighbourhood(x: i32, y: i32) -> u32 {
+    return neighbour_active(x, triangle_base_row(x, y)) |
+           neighbour_active(x - 1, y) << 1u |
+           neighbour_active(x + 1, y) << 2u;
+}
+
+// Write a cell's next state, and count how many generations the cell has been in its current
+// state, i.e. how long it has been alive, or how long since it died. The count saturates
+// rather than wrapping.
+fn set_next_state(i: u32, state: u32, next_state: u32) {
+    cell_state_out[i] = next_state;
+    let age = cell_age_in[i];
+    cell_age_out[i] = select(1u, min(age, 0xfffffffeu) + 1u, next_state == state);
+}
+
+// The next state of a cell under a Life-like, Generations or Larger than Life rule, given
+// whether it would be born or survive
+fn next_life_state(state: u32, born: bool, survives: bool) -> u32 {
+    if (state == 0u) {
+        // Dead cells use the birth condition
+        return select(0u, 1u, born);
+    } else if (state == 1u && survives) {
+        // Live cells use the survive condition
+        return 1u;
+    } else if (state + 1u < rule.states) {
+        // Cells that don't survive start dying, and dying cells keep dying
+        return state + 1u;
+    }
+    return 0u;
+}
+
+// Life-like and Generations rules
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) cell: vec3<u32>) {
+    let size = vec2<u32>(grid.xy);
+
+    // The grid dimensions aren't always a multiple of the workgroup size,
+    // so skip any invocations that fall outside the grid.
+    if (cell.x >= size.x || cell.y >= size.y) {
+        return;
+    }
+
+    // Look up the rule's transitions for the arrangement of active neighbours around this cell
+    let x = i32(cell.x);
+    let y = i32(cell.y);
+    var neighbourhood: u32;
+    switch (rule.neighbourhood) {
+        case NEIGHBOURHOOD_HEXAGONAL: {
+            neighbourhood = active_hexagonal_neighbourhood(x, y);
+        }
+        case NEIGHBOURHOOD_TRIANGULAR: {
+            neighbourhood = active_triangular_neighbourhood(x, y);
+        }
+        case NEIGHBOURHOOD_TRIANGULAR_EDGES: {
+            neighbourhood = active_triangular_edge_neighbourhood(x, y);
+        }
+        default: {
+            neighbourhood = active_neighbourhood(x, y);
+        }
+    }
+    let transitions = rule_table[neighbourhood];
+
+    let i = cell.y * size.x + cell.x;
+
+    // Apply the rule. Dead cells use the birth bit and active cells use the survive bit.
+    let state = cell_state_in[i];
+    let born = (transitions & 1u) == 1u;
+    let survives = (transitions & 2u) == 2u;
+    set_next_state(i, state, next_life_state(state, born, survives));
+}
+
+// Wireworld
+@compute @workgroup_size(8, 8)
+fn cs_wireworld(@builtin(global_invocation_id) cell: vec3<u32>) {
+    let size = vec2<u32>(grid.xy);
+    if (cell.x >= size.x || cell.y >= size.y) {
+        return;
+    }
+
+    let i = cell.y * size.x + cell.x;
+    let state = cell_state_in[i];
+    var next_state = state;
+    switch state {
+        case WIREWORLD_HEAD: {
+            next_state = WIREWORLD_TAIL;
+        }
+        case WIREWORLD_TAIL: {
+            next_state = WIREWORLD_CONDUCTOR;
+        }
+        case WIREWORLD_CONDUCTOR: {
+            // Electron heads are the active cells
+            let heads = count_active_neighbours(i32(cell.x), i32(cell.y));
+            if (heads == 1u || heads == 2u) {
+                next_state = WIREWORLD_HEAD;
+            }
+        }
+        default: {}
+    }
+    set_next_state(i, state, next_state);
+}
+
+// Larger than Life
+
+// Must match MAX_RADIUS in rule.rs
+const LTL_MAX_RADIUS: u32 = 10u;
+// The cells a workgroup reads: its own 8x8 cells plus a margin of up to LTL_MAX_RADIUS around them
+const LTL_MAX_TILE_SIZE: u32 = 8u + 2u * LTL_MAX_RADIUS;
+// LTL_MAX_TILE_SIZE * LTL_MAX_TILE_SIZE, as array sizes can't be expressions
+const LTL_MAX_TILE_CELLS: u32 = 784u;
+
+// Whether each cell in the workgroup's tile is active. Neighbourhoods overlap heavily, so each
+// cell is looked up once per workgroup rather than once per neighbour.
+var<workgroup> ltl_tile: array<u32, LTL_MAX_TILE_CELLS>;
+
+@compute @workgroup_size(8, 8)
+fn cs_larger_than_life(
+    @builtin(global_invocation_id) cell: vec3<u32>,
+    @builtin(local_invocation_id) local: vec3<u32>,
+    @builtin(workgroup_id) workgroup: vec3<u32>,
+) {
+    let size = vec2<u32>(grid.xy);
+    let radius = i32(min(rule.radius, LTL_MAX_RADIUS));
+
+    // Load the tile, which is only as large as the radius needs, with every invocation loading
+    // a share of it. The tile's origin is radius cells below and left of the workgroup's first
+    // cell, and may be outside the grid.
+    let tile_size = 8 + 2 * radius;
+    let origin = vec2<i32>(workgroup.xy * 8u) - radius;
+    let tile_cells = u32(tile_size * tile_size);
+    for (var t = local.y * 8u + local.x; t < tile_cells; t += 64u) {
+        let offset = vec2<i32>(i32(t) % tile_size, i32(t) / tile_size);
+        ltl_tile[t] = neighbour_active(origin.x + offset.x, origin.y + offset.y);
+    }
+    workgroupBarrier();
+
+    // Skip invocations outside the grid, now that they've helped to load the tile
+    if (cell.x >= size.x || cell.y >= size.y) {
+        return;
+    }
+
+    // Count the active cells in the neighbourhood, which is centred on this cell's place in
+    // the tile
+    let centre = vec2<i32>(local.xy) + radius;
+    var active_neighbours = 0u;
+    for (var dy = -radius; dy <= radius; dy++) {
+        for (var dx = -radius; dx <= radius; dx++) {
+            let in_neighbourhood = rule.neighbourhood != NEIGHBOURHOOD_VON_NEUMANN ||
+                abs(dx) + abs(dy) <= radius;
+            let is_middle = dx == 0 && dy == 0;
+            if (in_neighbourhood && (!is_middle || rule.middle == 1u)) {
+                active_neighbours += ltl_tile[(centre.y + dy) * tile_size + centre.x + dx];
+            }
+        }
+    }
+
+    let i = cell.y * size.x + cell.x;
+    let state = cell_state_in[i];
+    let born = rule.birth_range.x <= active_neighbours && active_neighbours <= rule.birth_range.y;
+    let survives = rule.survive_range.x <= active_neighbours &&
+        active_neighbours <= rule.survive_range.y;
+    set_next_state(i, state, next_life_state(state, born, survives));
+}
+
+// Lenia
+
+// Must match MAX_LENIA_RADIUS in rule.rs
+const LENIA_MAX_RADIUS: u32 = 32u;
+
+// How much a cell's value grows, from -1 to 1, given its potential
+fn lenia_growth(potential: f32) -> f32 {
+    let distance = potential - rule.growth_centre;
+    let width = rule.growth_width;
+    switch rule.growth {
+        case GROWTH_POLYNOMIAL: {
+            let bump = max(0.0, 1.0 - distance * distance / (9.0 * width * width));
+            let squared = bump * bump;
+            return 2.0 * squared * squared - 1.0;
+        }
+        case GROWTH_EXPONENTIAL: {
+            return 2.0 * exp(-distance * distance / (2.0 * width * width)) - 1.0;
+        }
+        case GROWTH_STEP: {
+            return select(-1.0, 1.0, abs(distance) <= width);
+        }
+        default: {
+            return -1.0;
+        }
+    }
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_lenia(@builtin(global_invocation_id) cell: vec3<u32>) {
+    let size = vec2<u32>(grid.xy);
+    if (cell.x >= size.x || cell.y >= size.y) {
+        return;
+    }
+
+    // The potential is the kernel's weighted average of the values around the cell. Cells
+    // outside the grid have no value.
+    let radius = i32(min(rule.radius, LENIA_MAX_RADIUS));
+    let diameter = 2 * radius + 1;
+    var potential = 0.0;
+    for (var dy = -radius; dy <= radius; dy++) {
+        for (var dx = -radius; dx <= radius; dx++) {
+            let weight = lenia_kernel[(dy + radius) * diameter + dx + radius];
+            let neighbour = neighbour_index(i32(cell.x) + dx, i32(cell.y) + dy);
+            if (weight != 0.0 && neighbour >= 0) {
+                potential += weight * cell_value_in[neighbour];
+            }
+        }
+    }
+
+    let i = cell.y * size.x + cell.x;
+    let value = clamp(cell_value_in[i] + lenia_growth(potential) * rule.time_step, 0.0, 1.0);
+    cell_value_out[i] = value;
+    set_next_state(i, cell_state_in[i], select(0u, 1u, value > 0.0));
+}
+
+// Elementary rules
+
+// Whether the cell at x in a row is alive (1) or not (0). The ends of the row join up unless
+// the grid is bounded, as a row has no other edges to cross.
+fn elementary_cell_active(x: i32, row: u32) -> u32 {
+    let width = i32(grid.x);
+    let outside = x < 0 || x >= width;
+    if (outside && topology.kind == TOPOLOGY_BOUNDED) {
+        return 0u;
+    }
+    let wrapped = u32((x + width) % width);
+    return select(0u, 1u, cell_state_in[row * u32(width) + wrapped] == 1u);
+}
+
+// Each invocation handles a column, as each generation is a single row
+@compute @workgroup_size(64)
+fn cs_elementary(@builtin(global_invocation_id) cell: vec3<u32>) {
+    let size = vec2<u32>(grid.xy);
+    if (cell.x >= size.x) {
+        return;
+    }
+    let row = history.row;
+    let next_row = (row + 1u) % size.y;
+
+    // Only the new row is written. Each step copies it back into the input buffer afterwards, so
+    // both buffers hold the whole history, which vs_main draws in order from history.row.
+
+    // The rule table is indexed with the left, middle and right cells, most significant first
+    let x = i32(cell.x);
+    let neighbourhood = elementary_cell_active(x - 1, row) << 2u |
+                        elementary_cell_active(x, row) << 1u |
+                        elementary_cell_active(x + 1, row);
+    let next_state = rule_table[neighbourhood];
+
+    // A cell's age is how many generations its column has been in its state
+    let previous = row * size.x + cell.x;
+    let i = next_row * size.x + cell.x;
+    let age = cell_age_in[previous];
+    cell_state_out[i] = next_state;
+    cell_age_out[i] = select(1u, min(age, 0xfffffffeu) + 1u, next_state == cell_state_in[previous]);
+}
+
+// 3D rules
+
+// The index of the neighbour at (x, y) in layer z, or -1 if there's no cell there. Each layer's
+// edges join up as a 2D grid's do, and the layers wrap around unless the grid is bounded. See
+// Topology::wrap_layer in topology.rs.
+fn neighbour_index_3d(x: i32, y: i32, z: i32) -> i32 {
+    let depth = i32(grid.z);
+    let wraps = wrap_count(z, depth);
+    let i = neighbour_index(x, y);
+    if (i < 0 || (wraps != 0 && topology.kind == TOPOLOGY_BOUNDED)) {
+        return -1;
+    }
+    let layer = z - depth * wraps;
+    return layer * i32(grid.x) * i32(grid.y) + i;
+}
+
+// Each invocation handles a cell of a cube of cells, counting the 26 cells around it
+@compute @workgroup_size(4, 4, 4)
+fn cs_life_3d(@builtin(global_invocation_id) cell: vec3<u32>) {
+    let size = vec3<u32>(grid);
+    if (any(cell >= size)) {
+        return;
+    }
+
+    let centre = vec3<i32>(cell);
+    var active_neighbours = 0u;
+    for (var dz = -1; dz <= 1; dz++) {
+        for (var dy = -1; dy <= 1; dy++) {
+            for (var dx = -1; dx <= 1; dx++) {
+                if (dx == 0 && dy == 0 && dz == 0) {
+                    continue;
+                }
+                let neighbour = neighbour_index_3d(centre.x + dx, centre.y + dy, centre.z + dz);
+                if (neighbour >= 0 && cell_state_in[neighbour] == 1u) {
+                    active_neighbours++;
+                }
+            }
+        }
+    }
+
+    let i = (cell.z * size.y + cell.y) * size.x + cell.x;
+    let state = cell_state_in[i];
+    let born = (rule.birth_counts >> active_neighbours & 1u) != 0u;
+    let survives = (rule.survive_counts >> active_neighbours & 1u) != 0u;
+    set_next_state(i, state, next_life_state(state, born, survives));
+}
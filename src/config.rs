@@ -0,0 +1,352 @@
+// Startup options for the simulation.
+// On desktop these are parsed from the command line, on the web the defaults are used.
+
+use crate::palette::Palette;
+use crate::rle::Pattern;
+use crate::rule::Rule;
+use crate::scheduler::{
+    DEFAULT_GENERATIONS_PER_SECOND, MAX_GENERATIONS_PER_SECOND, MIN_GENERATIONS_PER_SECOND,
+};
+use crate::style::MAX_TRAIL_LENGTH;
+use crate::topology::Topology;
+
+pub const DEFAULT_GRID_SIZE: u32 = 64;
+// 3D grids have as many cells in each layer, so they default to fewer cells across
+pub const DEFAULT_VOLUME_SIZE: u32 = 32;
+
+// Bounds for the grid dimensions, both at startup and when resizing at runtime.
+pub const MIN_GRID_SIZE: u32 = 8;
+pub const MAX_GRID_SIZE: u32 = 4096;
+// 3D grids are bounded by their number of cells too, as large as the largest 2D grid
+pub const MAX_GRID_CELLS: u32 = MAX_GRID_SIZE * MAX_GRID_SIZE;
+
+// The dimensions a 2D grid gets when a 3D rule takes it over. It gets as many layers as the
+// default volume has, or fewer if its layers are large, but no fewer than the smallest grid
+// size. Layers too large for even that many are shrunk to fit the cell limit, keeping their
+// shape.
+pub(crate) fn default_volume(width: u32, height: u32) -> (u32, u32, u32) {
+    let layer_cells = width * height;
+    let depth = (MAX_GRID_CELLS / layer_cells).clamp(MIN_GRID_SIZE, DEFAULT_VOLUME_SIZE);
+    let max_layer_cells = MAX_GRID_CELLS / depth;
+    if layer_cells <= max_layer_cells {
+        return (width, height, depth);
+    }
+    let scale = (max_layer_cells as f64 / layer_cells as f64).sqrt();
+    let shrink = |size: u32| ((size as f64 * scale) as u32).max(MIN_GRID_SIZE);
+    (shrink(width), shrink(height), depth)
+}
+
+// The grid dimensions nearest to those asked for that are within the limits and suit the
+// rule, or None if they would have too many cells. The depth is ignored unless the rule is 3D.
+pub(crate) fn clamp_grid_size(
+    rule: Rule,
+    width: u32,
+    height: u32,
+    depth: u32,
+) -> Option<(u32, u32, u32)> {
+    let width = width.clamp(MIN_GRID_SIZE, MAX_GRID_SIZE);
+    let height = height.clamp(MIN_GRID_SIZE, MAX_GRID_SIZE);
+    // The limits are even, so rounding up keeps the dimensions within them
+    let (width, height) = rule.lattice().fit_grid_size(width, height);
+    let depth = if rule.is_three_dimensional() {
+        depth.clamp(MIN_GRID_SIZE, MAX_GRID_SIZE)
+    } else {
+        1
+    };
+    let cells = width as u64 * height as u64 * depth as u64;
+    (cells <= MAX_GRID_CELLS as u64).then_some((width, height, depth))
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub grid_width: u32,
+    pub grid_height: u32,
+    // The number of layers, which is 1 unless the rule is 3D
+    pub grid_depth: u32,
+    pub rule: Rule,
+    pub topology: Topology,
+    pub generations_per_second: f64,
+    // How many generations dead cells fade out over, if trails are shown from the start
+    pub trail_length: Option<u32>,
+    // User palettes, which are added after the built-in ones
+    pub palettes: Vec<Palette>,
+    // The name of the palette to start with, instead of the first built-in palette
+    pub palette: Option<String>,
+    // Pattern placed in the centre of the grid at startup, instead of a random grid
+    pub pattern: Option<Pattern>,
+    // Check the GPU simulation against the CPU reference for this many generations, instead of
+    // opening a window
+    pub verify_generations: Option<u32>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            grid_width: DEFAULT_GRID_SIZE,
+            grid_height: DEFAULT_GRID_SIZE,
+            grid_depth: 1,
+            rule: Rule::default(),
+            topology: Topology::default(),
+            generations_per_second: DEFAULT_GENERATIONS_PER_SECOND,
+            trail_length: None,
+            palettes: Vec::new(),
+            palette: None,
+            pattern: None,
+            verify_generations: None,
+        }
+    }
+}
+
+pub const USAGE: &str = "\
+Usage: life-wgpu [OPTIONS]
+
+Options:
+    --grid <WIDTHxHEIGHT>   Grid dimensions, e.g. 512x512 or 300x120 (default 64x64). 3D
+                            rules add a depth, e.g. 48x48x24 (default 32x32x32)
+    --rule <RULESTRING>     Life-like rule in B/S notation, e.g. B36/S23 (default B3/S23),
+                            optionally with Hensel letters, e.g. B2-a/S12, or ending in H
+                            for the hexagonal lattice, e.g. B2/S34H, or L or LE for the
+                            triangular lattice, e.g. B45/S3456L, Generations rule
+                            in B/S/C notation, e.g. B2/S/C3, Larger than Life rule, e.g.
+                            R5,C0,M1,S34..58,B34..45,NM, Lenia rule, e.g.
+                            R=13;T=10;b=1;m=0.15;s=0.015;kn=1;gn=1, Lenia for Orbium,
+                            WireWorld, an elementary rule, e.g. W30, or a 3D rule in
+                            Bays' notation, e.g. 4555, or with comma separated counts,
+                            e.g. B5/S4,5
+    --topology <TOPOLOGY>   How the grid's edges join up: bounded, torus, klein, cross or
+                            cylinder (default torus)
+    --speed <GENERATIONS>   Generations simulated per second, may be fractional (default 10)
+    --trail <GENERATIONS>   Show trails of dead cells fading out over this many generations
+    --palettes <FILE>       Load extra colour palettes from a palette file
+    --palette <NAME>        Palette to start with, e.g. Classic (default Lamp)
+    --pattern <FILE>        RLE pattern to start from. Its rule is used unless --rule is given
+    --verify <GENERATIONS>  Step the GPU and CPU simulations in lockstep without opening a
+                            window, reporting the first cell where they diverge
+    -h, --help              Print this message";
+
+impl Config {
+    // Parse the command line arguments (excluding the program name).
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+        let mut rule_given = false;
+        let mut grid_given = false;
+        let mut depth = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--grid" => {
+                    let value = args.next().ok_or("--grid requires a value")?;
+                    (config.grid_width, config.grid_height, depth) = parse_grid_size(&value)?;
+                    grid_given = true;
+                }
+                "--rule" => {
+                    let value = args.next().ok_or("--rule requires a value")?;
+                    config.rule = value
+                        .parse()
+                        .map_err(|e| format!("Invalid rule '{value}': {e}"))?;
+                    rule_given = true;
+                }
+                "--topology" => {
+                    let value = args.next().ok_or("--topology requires a value")?;
+                    config.topology = value.parse().map_err(|e| format!("{e}"))?;
+                }
+                "--speed" => {
+                    let value = args.next().ok_or("--speed requires a value")?;
+                    let speed = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|speed| {
+                            (MIN_GENERATIONS_PER_SECOND..=MAX_GENERATIONS_PER_SECOND)
+                                .contains(speed)
+                        })
+                        .ok_or_else(|| {
+                            format!(
+                                "Invalid speed '{value}', expected {MIN_GENERATIONS_PER_SECOND} to \
+                                 {MAX_GENERATIONS_PER_SECOND} generations per second"
+                            )
+                        })?;
+                    config.generations_per_second = speed;
+                }
+                "--trail" => {
+                    let value = args.next().ok_or("--trail requires a value")?;
+                    let trail_length = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|length| (1..=MAX_TRAIL_LENGTH).contains(length))
+                        .ok_or_else(|| {
+                            format!(
+                                "Invalid trail length '{value}', expected 1 to \
+                                 {MAX_TRAIL_LENGTH} generations"
+                            )
+                        })?;
+                    config.trail_length = Some(trail_length);
+                }
+                "--palettes" => {
+                    let path = args.next().ok_or("--palettes requires a value")?;
+                    let text = std::fs::read_to_string(&path)
+                        .map_err(|e| format!("Failed to read palettes '{path}': {e}"))?;
+                    let palettes = Palette::parse_file(&text)
+                        .map_err(|e| format!("Invalid palettes '{path}': {e}"))?;
+                    config.palettes.extend(palettes);
+                }
+                "--palette" => {
+                    let name = args.next().ok_or("--palette requires a value")?;
+                    config.palette = Some(name);
+                }
+                "--pattern" => {
+                    let path = args.next().ok_or("--pattern requires a value")?;
+                    let rle = std::fs::read_to_string(&path)
+                        .map_err(|e| format!("Failed to read pattern '{path}': {e}"))?;
+                    let pattern = Pattern::from_rle(&rle)
+                        .map_err(|e| format!("Invalid pattern '{path}': {e}"))?;
+                    config.pattern = Some(pattern);
+                }
+                "--verify" => {
+                    let value = args.next().ok_or("--verify requires a value")?;
+                    let generations = value
+                        .parse()
+                        .map_err(|_| format!("Invalid number of generations '{value}'"))?;
+                    config.verify_generations = Some(generations);
+                }
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                _ => return Err(format!("Unknown argument '{arg}'\n\n{USAGE}")),
+            }
+        }
+
+        if !rule_given {
+            if let Some(rule) = config.pattern.as_ref().and_then(|pattern| pattern.rule) {
+                config.rule = rule;
+            }
+        }
+
+        // Only 3D rules have a depth, which is known once the rule is. They default to a cube.
+        match (depth, config.rule.is_three_dimensional()) {
+            (Some(depth), true) => config.grid_depth = depth,
+            (None, true) if !grid_given => {
+                config.grid_width = DEFAULT_VOLUME_SIZE;
+                config.grid_height = DEFAULT_VOLUME_SIZE;
+                config.grid_depth = DEFAULT_VOLUME_SIZE;
+            }
+            (None, true) => return Err("3D rules need a grid depth, e.g. 32x32x32".to_string()),
+            (Some(_), false) => return Err("Only 3D rules have a grid depth".to_string()),
+            (None, false) => {}
+        }
+        if config.rule.is_three_dimensional() && !config.rule.supports_topology(config.topology) {
+            return Err(format!(
+                "3D rules can't be used with the {} topology, only bounded or torus",
+                config.topology
+            ));
+        }
+        config
+            .rule
+            .lattice()
+            .check_grid(config.topology, config.grid_width, config.grid_height)?;
+        let cells = config.grid_width as u64 * config.grid_height as u64 * config.grid_depth as u64;
+        if cells > MAX_GRID_CELLS as u64 {
+            return Err(format!(
+                "Grid of {cells} cells is too large (at most {MAX_GRID_CELLS})"
+            ));
+        }
+
+        // The palette is looked up once all the palette files have been loaded
+        if let Some(name) = &config.palette {
+            let known = Palette::built_in()
+                .iter()
+                .chain(&config.palettes)
+                .any(|palette| palette.name.eq_ignore_ascii_case(name));
+            if !known {
+                return Err(format!("Unknown palette '{name}'"));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+// Parse WIDTHxHEIGHT, or WIDTHxHEIGHTxDEPTH for a 3D grid
+fn parse_grid_size(value: &str) -> Result<(u32, u32, Option<u32>), String> {
+    let dimensions: Vec<&str> = value.split(['x', 'X']).collect();
+    let (width, height, depth) = match dimensions[..] {
+        [width, height] => (width, height, None),
+        [width, height, depth] => (width, height, Some(depth)),
+        _ => {
+            return Err(format!(
+                "Invalid grid size '{value}', expected WIDTHxHEIGHT"
+            ))
+        }
+    };
+
+    let parse_dimension = |dimension: &str| -> Result<u32, String> {
+        let size = dimension
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid grid dimension '{dimension}'"))?;
+        if !(MIN_GRID_SIZE..=MAX_GRID_SIZE).contains(&size) {
+            return Err(format!(
+                "Grid dimension {size} is out of range ({MIN_GRID_SIZE}..={MAX_GRID_SIZE})"
+            ));
+        }
+        Ok(size)
+    };
+
+    Ok((
+        parse_dimension(width)?,
+        parse_dimension(height)?,
+        depth.map(parse_dimension).transpose()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_volumes_fit_and_can_be_halved() {
+        let rule: Rule = "4555".parse().unwrap();
+        for (width, height) in [(8, 8), (64, 64), (512, 512), (4096, 3000), (4096, 4096)] {
+            let (width, height, depth) = default_volume(width, height);
+            assert!(depth >= MIN_GRID_SIZE);
+            assert_eq!(
+                clamp_grid_size(rule, width, height, depth),
+                Some((width, height, depth))
+            );
+            assert!(clamp_grid_size(rule, width / 2, height / 2, depth / 2).is_some());
+        }
+        assert_eq!(default_volume(64, 64), (64, 64, DEFAULT_VOLUME_SIZE));
+        assert_eq!(
+            default_volume(MAX_GRID_SIZE, MAX_GRID_SIZE),
+            (1448, 1448, MIN_GRID_SIZE)
+        );
+    }
+
+    #[test]
+    fn three_dimensional_rules_only_wrap_as_a_torus() {
+        let args = |topology: &str| {
+            let args = ["--rule", "4555", "--topology", topology];
+            Config::from_args(args.into_iter().map(String::from))
+        };
+        assert!(args("torus").is_ok());
+        assert!(args("bounded").is_ok());
+        for topology in ["cylinder", "klein", "cross"] {
+            assert!(args(topology).is_err(), "{topology} should be rejected");
+        }
+    }
+
+    #[test]
+    fn grid_sizes_are_clamped_to_the_limits() {
+        let life = Rule::CONWAY;
+        assert_eq!(
+            clamp_grid_size(life, 1, 10_000, 5),
+            Some((MIN_GRID_SIZE, MAX_GRID_SIZE, 1))
+        );
+        let hexagonal: Rule = "B2/S34H".parse().unwrap();
+        assert_eq!(clamp_grid_size(hexagonal, 9, 9, 1), Some((9, 10, 1)));
+        let three_dimensional: Rule = "4555".parse().unwrap();
+        assert_eq!(
+            clamp_grid_size(three_dimensional, 64, 64, 1),
+            Some((64, 64, MIN_GRID_SIZE))
+        );
+        assert_eq!(clamp_grid_size(three_dimensional, 4096, 4096, 8), None);
+    }
+}
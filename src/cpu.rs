@@ -0,0 +1,894 @@
+// A pure Rust implementation of the simulation step in compute.wgsl.
+// It's used as a reference to check the GPU against, and as a fallback when no adapter is found.
+
+use crate::config::default_volume;
+use crate::rle::Pattern;
+use crate::rule::{
+    LargerThanLifeRule, LeniaFunction, LeniaRule, Life3DRule, LifeNeighbourhood, LifeRule,
+    Neighbourhood, Rule, WIREWORLD_CONDUCTOR, WIREWORLD_HEAD, WIREWORLD_TAIL,
+};
+use crate::topology::Topology;
+
+pub struct CpuSimulation {
+    width: u32,
+    height: u32,
+    depth: u32, // the number of layers, which is 1 unless the grid is 3D
+    rule: Rule,
+    topology: Topology,
+    // The rule compiled for next_life_state, as it's uploaded to the GPU
+    lookup_table: Vec<u32>,
+    // The Lenia kernel for next_lenia_value, as it's uploaded to the GPU
+    kernel_weights: Vec<f32>,
+    generation: u32, // how many simulation steps have been run
+    cells: Vec<u32>,
+    next_cells: Vec<u32>,
+    // The value of each cell for continuous rules. These are empty for other rules.
+    values: Vec<f32>,
+    next_values: Vec<f32>,
+}
+
+impl CpuSimulation {
+    // Create a simulation with an empty grid
+    pub fn new(width: u32, height: u32, rule: Rule) -> Self {
+        Self::with_depth(width, height, 1, rule)
+    }
+
+    // Create a simulation with an empty grid of depth layers
+    fn with_depth(width: u32, height: u32, depth: u32, rule: Rule) -> Self {
+        let num_cells = (width * height * depth) as usize;
+        let num_values = if rule.is_continuous() { num_cells } else { 0 };
+        Self {
+            width,
+            height,
+            depth,
+            rule,
+            topology: Topology::default(),
+            lookup_table: rule.lookup_table(),
+            kernel_weights: rule.kernel_weights(),
+            generation: 0,
+            cells: vec![0; num_cells],
+            next_cells: vec![0; num_cells],
+            values: vec![0.0; num_values],
+            next_values: vec![0.0; num_values],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    // How many generations have been simulated since the grid was last reset
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    // Switch to a different rule. As on the GPU, switching between a continuous and a discrete
+    // rule, or between a 2D and a 3D rule, clears the grid, 2D rules only keep one layer, and
+    // 3D rules turn a 2D grid into its default volume.
+    pub fn set_rule(&mut self, rule: Rule) {
+        let continuity_changed = rule.is_continuous() != self.rule.is_continuous();
+        let dimensions_changed = rule.is_three_dimensional() != self.rule.is_three_dimensional();
+        self.rule = rule;
+        self.lookup_table = rule.lookup_table();
+        self.kernel_weights = rule.kernel_weights();
+        if continuity_changed || dimensions_changed {
+            let (width, height, depth) = if !rule.is_three_dimensional() {
+                (self.width, self.height, 1)
+            } else if dimensions_changed {
+                default_volume(self.width, self.height)
+            } else {
+                (self.width, self.height, self.depth)
+            };
+            self.resize_3d(width, height, depth);
+        }
+    }
+
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+    }
+
+    // Advance the simulation by n generations
+    pub fn step(&mut self, n: u32) {
+        for _ in 0..n {
+            if let Rule::Lenia(rule) = self.rule {
+                // Lenia's states follow from its values, so the values are stepped first
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        let i = self.cell_index(x, y);
+                        self.next_values[i] = self.next_lenia_value(rule, x, y);
+                    }
+                }
+                std::mem::swap(&mut self.values, &mut self.next_values);
+            }
+            for z in 0..self.depth {
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        let i = self.cell_index_3d(x, y, z);
+                        self.next_cells[i] = self.next_state(x, y, z);
+                    }
+                }
+            }
+            std::mem::swap(&mut self.cells, &mut self.next_cells);
+            self.generation += 1;
+        }
+    }
+
+    // The current generation, as row-major cell states, one layer after another
+    pub fn read_cells(&self) -> Vec<u32> {
+        self.cells.clone()
+    }
+
+    // Replace the current generation with row-major cell states, one layer after another
+    pub fn write_cells(&mut self, cells: &[u32]) {
+        assert_eq!(
+            cells.len(),
+            self.cells.len(),
+            "cell count doesn't match the {}x{}x{} grid",
+            self.width,
+            self.height,
+            self.depth
+        );
+        self.cells.copy_from_slice(cells);
+        if self.rule.is_continuous() {
+            self.values = cells
+                .iter()
+                .map(|&cell| Self::initial_value(cell))
+                .collect();
+        }
+    }
+
+    // The values of the current generation's cells, in row-major order. Only continuous rules
+    // have values, so this is empty for other rules.
+    pub fn read_values(&self) -> Vec<f32> {
+        self.values.clone()
+    }
+
+    // Replace the values of the current generation's cells, in row-major order, for continuous
+    // rules. Their states should already have been written to match.
+    pub fn write_values(&mut self, values: &[f32]) {
+        assert!(
+            self.rule.is_continuous(),
+            "only continuous rules have cell values"
+        );
+        assert_eq!(
+            values.len(),
+            self.values.len(),
+            "value count doesn't match the {}x{} grid",
+            self.width,
+            self.height
+        );
+        self.values.copy_from_slice(values);
+    }
+
+    // Set a single cell of the current generation
+    pub fn write_cell(&mut self, x: u32, y: u32, value: u32) {
+        let i = self.cell_index(x, y);
+        self.cells[i] = value;
+        if self.rule.is_continuous() {
+            self.values[i] = Self::initial_value(value);
+        }
+    }
+
+    pub fn randomise(&mut self) {
+        let mut rng = rand::thread_rng();
+        if self.rule.is_continuous() {
+            for (cell, value) in self.cells.iter_mut().zip(self.values.iter_mut()) {
+                *value = self.rule.random_value(&mut rng);
+                *cell = (*value > 0.0) as u32;
+            }
+        } else {
+            // As on the GPU, one dimensional rules only start with the first row
+            let cells = if self.rule.is_one_dimensional() {
+                &mut self.cells[..self.width as usize]
+            } else {
+                &mut self.cells[..]
+            };
+            for cell in cells.iter_mut() {
+                *cell = self.rule.random_state(&mut rng);
+            }
+        }
+        self.generation = 0;
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(0);
+        self.values.fill(0.0);
+        self.generation = 0;
+    }
+
+    // Clear the grid and place a pattern in its centre
+    pub fn load_pattern(&mut self, pattern: &Pattern) {
+        self.cells = pattern.starting_cells(self.rule, self.width, self.height, self.depth);
+        if self.rule.is_continuous() {
+            self.values = self
+                .cells
+                .iter()
+                .map(|&cell| Self::initial_value(cell))
+                .collect();
+        }
+        self.generation = 0;
+    }
+
+    // Change the grid dimensions, keeping the number of layers. The new grid is empty.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.resize_3d(width, height, self.depth);
+    }
+
+    // Change the grid dimensions, including the number of layers of a 3D grid. The new grid is
+    // empty.
+    pub fn resize_3d(&mut self, width: u32, height: u32, depth: u32) {
+        let topology = self.topology;
+        *self = Self::with_depth(width, height, depth, self.rule);
+        self.topology = topology;
+    }
+
+    fn cell_index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    fn cell_index_3d(&self, x: u32, y: u32, z: u32) -> usize {
+        ((z * self.height + y) * self.width + x) as usize
+    }
+
+    // Same as initial_values in simulation.rs: the value of a cell whose state was just set
+    fn initial_value(state: u32) -> f32 {
+        (state == 1) as u32 as f32
+    }
+
+    // Same as count_active_neighbours in compute.wgsl: how many of the 8 cells around (x, y)
+    // are active
+    fn count_active_neighbours(&self, x: u32, y: u32) -> u32 {
+        // Neighbour coordinates, which are signed as they may fall outside the grid
+        let (x, y) = (x as i64, y as i64);
+        let left = x - 1;
+        let right = x + 1;
+        let down = y - 1;
+        let up = y + 1;
+
+        self.neighbour_active(right, up)
+            + self.neighbour_active(right, y)
+            + self.neighbour_active(right, down)
+            + self.neighbour_active(x, down)
+            + self.neighbour_active(left, down)
+            + self.neighbour_active(left, y)
+            + self.neighbour_active(left, up)
+            + self.neighbour_active(x, up)
+    }
+
+    // Same as active_neighbourhood in compute.wgsl: which of the 8 cells around (x, y) are
+    // active, with a bit for each in reading order
+    fn active_neighbourhood(&self, x: u32, y: u32) -> u8 {
+        let (x, y) = (x as i64, y as i64);
+        let left = x - 1;
+        let right = x + 1;
+        let down = y - 1;
+        let up = y + 1;
+
+        [
+            (left, up),
+            (x, up),
+            (right, up),
+            (left, y),
+            (right, y),
+            (left, down),
+            (x, down),
+            (right, down),
+        ]
+        .into_iter()
+        .enumerate()
+        .fold(0, |neighbourhood, (bit, (x, y))| {
+            neighbourhood | (self.neighbour_active(x, y) as u8) << bit
+        })
+    }
+
+    // Same as active_hexagonal_neighbourhood in compute.wgsl: the 6 cells around (x, y) on the
+    // hexagonal lattice, where odd rows are shifted right by half a cell
+    fn active_hexagonal_neighbourhood(&self, x: u32, y: u32) -> u8 {
+        let (x, y) = (x as i64, y as i64);
+        let shift = y & 1;
+        let left = x - 1 + shift;
+        let right = x + shift;
+        let down = y - 1;
+        let up = y + 1;
+
+        [
+            (left, up),
+            (right, up),
+            (x - 1, y),
+            (x + 1, y),
+            (left, down),
+            (right, down),
+        ]
+        .into_iter()
+        .enumerate()
+        .fold(0, |neighbourhood, (bit, (x, y))| {
+            neighbourhood | (self.neighbour_active(x, y) as u8) << bit
+        })
+    }
+
+    // Same as triangle_base_row in compute.wgsl: the row a triangle shares its base with
+    fn triangle_base_row(x: i64, y: i64) -> i64 {
+        if (x + y) % 2 == 0 {
+            y - 1
+        } else {
+            y + 1
+        }
+    }
+
+    // Same as active_triangular_neighbourhood in compute.wgsl: the 12 cells sharing a corner
+    // with (x, y) on the triangular lattice, from the row its base touches to the row its tip
+    // touches
+    fn active_triangular_neighbourhood(&self, x: u32, y: u32) -> u16 {
+        let (x, y) = (x as i64, y as i64);
+        let base_row = Self::triangle_base_row(x, y);
+        let tip_row = 2 * y - base_row;
+
+        (-2..=2)
+            .map(|dx| (x + dx, base_row))
+            .chain([-2, -1, 1, 2].map(|dx| (x + dx, y)))
+            .chain((-1..=1).map(|dx| (x + dx, tip_row)))
+            .enumerate()
+            .fold(0, |neighbourhood, (bit, (x, y))| {
+                neighbourhood | (self.neighbour_active(x, y) as u16) << bit
+            })
+    }
+
+    // Same as active_triangular_edge_neighbourhood in compute.wgsl: the 3 cells sharing an edge
+    // with (x, y) on the triangular lattice
+    fn active_triangular_edge_neighbourhood(&self, x: u32, y: u32) -> u16 {
+        let (x, y) = (x as i64, y as i64);
+        [(x, Self::triangle_base_row(x, y)), (x - 1, y), (x + 1, y)]
+            .into_iter()
+            .enumerate()
+            .fold(0, |neighbourhood, (bit, (x, y))| {
+                neighbourhood | (self.neighbour_active(x, y) as u16) << bit
+            })
+    }
+
+    // Same as neighbour_active in compute.wgsl: whether the neighbour at (x, y), which may be
+    // outside the grid, is alive. Dying cells don't count, and in Wireworld the
+    // electron heads are the active cells.
+    fn neighbour_active(&self, x: i64, y: i64) -> u32 {
+        match self.topology.wrap(x, y, self.width, self.height) {
+            Some((x, y)) => (self.cells[self.cell_index(x, y)] == 1) as u32,
+            None => 0,
+        }
+    }
+
+    // The next state of the cell at (x, y) in layer z. Only 3D rules have more than one layer.
+    fn next_state(&self, x: u32, y: u32, z: u32) -> u32 {
+        match self.rule {
+            Rule::Life(rule) => self.next_life_state(rule, x, y),
+            Rule::Wireworld => self.next_wireworld_state(x, y),
+            Rule::LargerThanLife(rule) => self.next_larger_than_life_state(rule, x, y),
+            // The values have already been stepped
+            Rule::Lenia(_) => (self.values[self.cell_index(x, y)] > 0.0) as u32,
+            Rule::Elementary(_) => self.next_elementary_state(x, y),
+            Rule::Life3D(rule) => self.next_life_3d_state(rule, x, y, z),
+        }
+    }
+
+    // Same as cs_elementary in compute.wgsl: the row after the current generation's row, which
+    // replaces the oldest, is the next generation, and the rest of the history stays as it is
+    fn next_elementary_state(&self, x: u32, y: u32) -> u32 {
+        let row = self.generation % self.height;
+        if y != (row + 1) % self.height {
+            return self.cells[self.cell_index(x, y)];
+        }
+
+        // The ends of the row join up unless the grid is bounded
+        let active = |x: i64| -> u32 {
+            let width = self.width as i64;
+            if self.topology == Topology::Bounded && !(0..width).contains(&x) {
+                return 0;
+            }
+            (self.cells[self.cell_index(x.rem_euclid(width) as u32, row)] == 1) as u32
+        };
+        let x = x as i64;
+        let neighbourhood = active(x - 1) << 2 | active(x) << 1 | active(x + 1);
+        self.lookup_table[neighbourhood as usize]
+    }
+
+    // Same as next_life_state in compute.wgsl
+    fn apply_life_rule(states: u32, state: u32, born: bool, survives: bool) -> u32 {
+        match state {
+            // Dead cells use the birth condition
+            0 => born as u32,
+            // Live cells use the survive condition
+            1 if survives => 1,
+            // Cells that don't survive start dying, and dying cells keep dying
+            _ if state + 1 < states => state + 1,
+            _ => 0,
+        }
+    }
+
+    // Same as cs_main in compute.wgsl
+    fn next_life_state(&self, rule: LifeRule, x: u32, y: u32) -> u32 {
+        let state = self.cells[self.cell_index(x, y)];
+        let neighbourhood = match rule.neighbourhood {
+            LifeNeighbourhood::Moore => self.active_neighbourhood(x, y) as u16,
+            LifeNeighbourhood::Hexagonal => self.active_hexagonal_neighbourhood(x, y) as u16,
+            LifeNeighbourhood::Triangular => self.active_triangular_neighbourhood(x, y),
+            LifeNeighbourhood::TriangularEdges => self.active_triangular_edge_neighbourhood(x, y),
+        };
+        let transitions = self.lookup_table[neighbourhood as usize];
+
+        let born = transitions & 1 == 1;
+        let survives = transitions & 2 == 2;
+        Self::apply_life_rule(rule.states, state, born, survives)
+    }
+
+    // Same as cs_larger_than_life in compute.wgsl
+    fn next_larger_than_life_state(&self, rule: LargerThanLifeRule, x: u32, y: u32) -> u32 {
+        let state = self.cells[self.cell_index(x, y)];
+        let radius = rule.radius as i64;
+
+        let mut active_neighbours = 0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let in_neighbourhood = match rule.neighbourhood {
+                    Neighbourhood::Moore => true,
+                    Neighbourhood::VonNeumann => dx.abs() + dy.abs() <= radius,
+                };
+                let is_middle = dx == 0 && dy == 0;
+                if in_neighbourhood && (!is_middle || rule.middle) {
+                    active_neighbours += self.neighbour_active(x as i64 + dx, y as i64 + dy);
+                }
+            }
+        }
+
+        let in_range = |(min, max): (u32, u32)| (min..=max).contains(&active_neighbours);
+        Self::apply_life_rule(
+            rule.states,
+            state,
+            in_range(rule.birth),
+            in_range(rule.survive),
+        )
+    }
+
+    // Same as cs_life_3d in compute.wgsl
+    fn next_life_3d_state(&self, rule: Life3DRule, x: u32, y: u32, z: u32) -> u32 {
+        let state = self.cells[self.cell_index_3d(x, y, z)];
+
+        let mut active_neighbours = 0;
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if (dx, dy, dz) == (0, 0, 0) {
+                        continue;
+                    }
+                    let neighbour = (x as i64 + dx, y as i64 + dy, z as i64 + dz);
+                    let wrapped = self
+                        .topology
+                        .wrap(neighbour.0, neighbour.1, self.width, self.height)
+                        .zip(self.topology.wrap_layer(neighbour.2, self.depth));
+                    if let Some(((x, y), z)) = wrapped {
+                        active_neighbours += (self.cells[self.cell_index_3d(x, y, z)] == 1) as u32;
+                    }
+                }
+            }
+        }
+
+        let counted = |counts: u32| counts & (1 << active_neighbours) != 0;
+        Self::apply_life_rule(2, state, counted(rule.birth), counted(rule.survive))
+    }
+
+    // Same as cs_wireworld in compute.wgsl
+    fn next_wireworld_state(&self, x: u32, y: u32) -> u32 {
+        match self.cells[self.cell_index(x, y)] {
+            WIREWORLD_HEAD => WIREWORLD_TAIL,
+            WIREWORLD_TAIL => WIREWORLD_CONDUCTOR,
+            WIREWORLD_CONDUCTOR => match self.count_active_neighbours(x, y) {
+                1 | 2 => WIREWORLD_HEAD,
+                _ => WIREWORLD_CONDUCTOR,
+            },
+            state => state,
+        }
+    }
+
+    // Same as lenia_growth in compute.wgsl
+    fn lenia_growth(rule: LeniaRule, potential: f32) -> f32 {
+        let distance = potential - rule.growth_centre;
+        let width = rule.growth_width;
+        match rule.growth {
+            LeniaFunction::Polynomial => {
+                let bump = (1.0 - distance * distance / (9.0 * width * width)).max(0.0);
+                let squared = bump * bump;
+                2.0 * squared * squared - 1.0
+            }
+            LeniaFunction::Exponential => {
+                2.0 * (-distance * distance / (2.0 * width * width)).exp() - 1.0
+            }
+            LeniaFunction::Step if distance.abs() <= width => 1.0,
+            LeniaFunction::Step => -1.0,
+        }
+    }
+
+    // Same as cs_lenia in compute.wgsl
+    fn next_lenia_value(&self, rule: LeniaRule, x: u32, y: u32) -> f32 {
+        let radius = rule.radius as i64;
+        let diameter = 2 * radius + 1;
+
+        let mut potential = 0.0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let weight = self.kernel_weights[((dy + radius) * diameter + dx + radius) as usize];
+                if weight == 0.0 {
+                    continue;
+                }
+                let neighbour = (x as i64 + dx, y as i64 + dy);
+                if let Some((x, y)) =
+                    self.topology
+                        .wrap(neighbour.0, neighbour.1, self.width, self.height)
+                {
+                    potential += weight * self.values[self.cell_index(x, y)];
+                }
+            }
+        }
+
+        let time_step = 1.0 / rule.time_steps as f32;
+        let value = self.values[self.cell_index(x, y)];
+        (value + Self::lenia_growth(rule, potential) * time_step).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_VOLUME_SIZE;
+
+    fn life(width: u32, height: u32, cells: &[(u32, u32)]) -> CpuSimulation {
+        let mut simulation = CpuSimulation::new(width, height, "B3/S23".parse().unwrap());
+        for &(x, y) in cells {
+            simulation.write_cell(x, y, 1);
+        }
+        simulation
+    }
+
+    fn live_cells(simulation: &CpuSimulation) -> Vec<(u32, u32)> {
+        let width = simulation.width();
+        let cells = simulation.read_cells();
+        let mut live: Vec<_> = (0..cells.len() as u32)
+            .filter(|&i| cells[i as usize] != 0)
+            .map(|i| (i % width, i / width))
+            .collect();
+        live.sort();
+        live
+    }
+
+    // Whether each cell counts each other cell as a neighbour, found by making one cell alive
+    // at a time, for every topology the rule's lattice supports
+    fn assert_neighbours_are_symmetric(
+        rule: &str,
+        width: u32,
+        height: u32,
+        neighbourhood: fn(&CpuSimulation, u32, u32) -> u16,
+    ) {
+        let mut simulation = CpuSimulation::new(width, height, rule.parse().unwrap());
+        let cells: Vec<_> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .collect();
+        let mut topology = Topology::Bounded;
+        loop {
+            if simulation.rule().lattice().supports_topology(topology) {
+                simulation.set_topology(topology);
+                let neighbours = |simulation: &mut CpuSimulation, (x, y): (u32, u32)| {
+                    simulation.clear();
+                    simulation.write_cell(x, y, 1);
+                    cells
+                        .iter()
+                        .map(|&(x, y)| neighbourhood(simulation, x, y) != 0)
+                        .collect()
+                };
+                let sets: Vec<Vec<bool>> = cells
+                    .iter()
+                    .map(|&cell| neighbours(&mut simulation, cell))
+                    .collect();
+                for (i, &cell) in cells.iter().enumerate() {
+                    for (j, &other) in cells.iter().enumerate() {
+                        assert_eq!(
+                            sets[i][j], sets[j][i],
+                            "{cell:?} and {other:?} disagree about being neighbours on a \
+                             {width}x{height} {topology} grid"
+                        );
+                    }
+                }
+            }
+            topology = topology.next();
+            if topology == Topology::Bounded {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn hexagonal_neighbours_are_symmetric() {
+        assert_neighbours_are_symmetric("B2/S34H", 6, 4, |simulation, x, y| {
+            simulation.active_hexagonal_neighbourhood(x, y) as u16
+        });
+    }
+
+    #[test]
+    fn triangular_neighbours_are_symmetric() {
+        assert_neighbours_are_symmetric("B3/S23L", 6, 4, |simulation, x, y| {
+            simulation.active_triangular_neighbourhood(x, y)
+        });
+        assert_neighbours_are_symmetric("B2/S12LE", 6, 4, |simulation, x, y| {
+            simulation.active_triangular_edge_neighbourhood(x, y)
+        });
+    }
+
+    #[test]
+    fn switching_between_2d_and_3d_rules_changes_the_depth() {
+        let mut simulation = life(8, 8, &[(1, 1)]);
+        simulation.set_rule("4555".parse().unwrap());
+        assert_eq!(simulation.depth(), DEFAULT_VOLUME_SIZE);
+        assert_eq!(
+            simulation.read_cells(),
+            vec![0; 8 * 8 * DEFAULT_VOLUME_SIZE as usize]
+        );
+
+        // A 3D rule keeps the layers of another
+        simulation.resize_3d(8, 8, 4);
+        simulation.set_rule("5766".parse().unwrap());
+        assert_eq!(simulation.depth(), 4);
+
+        simulation.set_rule(Rule::CONWAY);
+        assert_eq!(simulation.depth(), 1);
+        assert_eq!(simulation.read_cells().len(), 8 * 8);
+    }
+
+    #[test]
+    fn blinker_has_period_2() {
+        let horizontal = vec![(2, 3), (3, 3), (4, 3)];
+        let mut simulation = life(8, 8, &horizontal);
+
+        simulation.step(1);
+        assert_eq!(live_cells(&simulation), vec![(3, 2), (3, 3), (3, 4)]);
+        simulation.step(1);
+        assert_eq!(live_cells(&simulation), horizontal);
+        assert_eq!(simulation.generation(), 2);
+    }
+
+    #[test]
+    fn glider_moves_one_cell_diagonally_every_4_generations() {
+        // The grid's first row is at the bottom, so this glider heads down and right
+        let glider = [(1, 4), (2, 3), (0, 2), (1, 2), (2, 2)];
+        let mut simulation = life(8, 8, &glider);
+
+        simulation.step(4);
+        let mut moved: Vec<_> = glider.iter().map(|&(x, y)| (x + 1, y - 1)).collect();
+        moved.sort();
+        assert_eq!(live_cells(&simulation), moved);
+
+        // It wraps around the torus back to where it started
+        simulation.step(28);
+        let mut start = glider.to_vec();
+        start.sort();
+        assert_eq!(live_cells(&simulation), start);
+    }
+
+    // A glider moving through an edge, which should carry on as it would on an infinite plane
+    // and only be mapped back onto the grid by the topology
+    fn assert_glider_crosses(topology: Topology, width: u32, height: u32, offset: (u32, u32)) {
+        let glider =
+            [(1, 4), (2, 3), (0, 2), (1, 2), (2, 2)].map(|(x, y)| (x + offset.0, y + offset.1));
+        let mut simulation = life(width, height, &glider);
+        simulation.set_topology(topology);
+        simulation.step(24);
+
+        let moved = |topology: Topology| {
+            let mut cells: Vec<_> = glider
+                .iter()
+                .map(|&(x, y)| {
+                    topology
+                        .wrap(x as i64 + 6, y as i64 - 6, width, height)
+                        .unwrap()
+                })
+                .collect();
+            cells.sort();
+            cells
+        };
+        assert_eq!(live_cells(&simulation), moved(topology));
+        assert_ne!(moved(topology), moved(Topology::Torus));
+    }
+
+    #[test]
+    fn gliders_are_mirrored_crossing_the_bottom_of_a_klein_bottle() {
+        assert_glider_crosses(Topology::KleinBottle, 16, 8, (0, 0));
+    }
+
+    #[test]
+    fn gliders_are_mirrored_crossing_the_edges_of_a_cross_surface() {
+        assert_glider_crosses(Topology::CrossSurface, 16, 8, (0, 0));
+        assert_glider_crosses(Topology::CrossSurface, 8, 16, (4, 7));
+    }
+
+    #[test]
+    fn torus_wraps_at_the_corners() {
+        // A block split across the four corners is only a block once the edges join up
+        let corners = vec![(0, 0), (0, 7), (7, 0), (7, 7)];
+        let mut simulation = life(8, 8, &corners);
+        simulation.step(1);
+        assert_eq!(live_cells(&simulation), corners);
+
+        let mut simulation = life(8, 8, &corners);
+        simulation.set_topology(Topology::Bounded);
+        simulation.step(1);
+        assert_eq!(live_cells(&simulation), vec![]);
+    }
+
+    #[test]
+    fn torus_births_across_the_corners() {
+        let mut simulation = life(8, 8, &[(0, 0), (7, 0), (0, 7)]);
+        simulation.step(1);
+        assert_eq!(
+            live_cells(&simulation),
+            vec![(0, 0), (0, 7), (7, 0), (7, 7)]
+        );
+    }
+
+    // The cells that aren't empty, with their states
+    fn cell_states(simulation: &CpuSimulation) -> Vec<(u32, u32, u32)> {
+        let width = simulation.width();
+        let cells = simulation.read_cells();
+        (0..cells.len() as u32)
+            .filter(|&i| cells[i as usize] != 0)
+            .map(|i| (i % width, i / width, cells[i as usize]))
+            .collect()
+    }
+
+    #[test]
+    fn brians_brain_cells_die_over_two_generations() {
+        let mut simulation = CpuSimulation::new(8, 8, "B2/S/C3".parse().unwrap());
+        simulation.write_cell(3, 3, 1);
+        simulation.write_cell(4, 3, 1);
+
+        // Nothing survives, so the pair starts dying as the cells next to both of them are born
+        simulation.step(1);
+        assert_eq!(
+            cell_states(&simulation),
+            vec![
+                (3, 2, 1),
+                (4, 2, 1),
+                (3, 3, 2),
+                (4, 3, 2),
+                (3, 4, 1),
+                (4, 4, 1)
+            ]
+        );
+
+        // The first pair is gone a generation later, as the cells born from it start dying in turn
+        simulation.step(1);
+        assert_eq!(
+            cell_states(&simulation),
+            vec![
+                (3, 1, 1),
+                (4, 1, 1),
+                (3, 2, 2),
+                (4, 2, 2),
+                (2, 3, 1),
+                (5, 3, 1),
+                (3, 4, 2),
+                (4, 4, 2),
+                (3, 5, 1),
+                (4, 5, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn wireworld_electrons_move_along_wires() {
+        let mut simulation = CpuSimulation::new(12, 5, Rule::Wireworld);
+        for x in 1..11 {
+            simulation.write_cell(x, 2, WIREWORLD_CONDUCTOR);
+        }
+        simulation.write_cell(1, 2, WIREWORLD_TAIL);
+        simulation.write_cell(2, 2, WIREWORLD_HEAD);
+
+        for head in 3..11 {
+            simulation.step(1);
+            let wire: Vec<_> = (1..11)
+                .map(|x| {
+                    let state = if x == head {
+                        WIREWORLD_HEAD
+                    } else if x == head - 1 {
+                        WIREWORLD_TAIL
+                    } else {
+                        WIREWORLD_CONDUCTOR
+                    };
+                    (x, 2, state)
+                })
+                .collect();
+            assert_eq!(cell_states(&simulation), wire);
+        }
+
+        // The electron runs off the end of the wire
+        simulation.step(2);
+        assert!(cell_states(&simulation)
+            .iter()
+            .all(|&(_, _, state)| state == WIREWORLD_CONDUCTOR));
+    }
+
+    #[test]
+    fn radius_1_larger_than_life_matches_life() {
+        // Counting the middle cell adds one to the counts of live cells
+        for rule in ["R1,C0,M0,S2..3,B3..3,NM", "R1,C0,M1,S3..4,B3..3,NM"] {
+            let mut life = CpuSimulation::new(16, 12, "B3/S23".parse().unwrap());
+            life.randomise();
+            let mut larger = CpuSimulation::new(16, 12, rule.parse().unwrap());
+            larger.write_cells(&life.read_cells());
+
+            for _ in 0..20 {
+                life.step(1);
+                larger.step(1);
+                assert_eq!(larger.read_cells(), life.read_cells(), "{rule}");
+            }
+        }
+    }
+
+    #[test]
+    fn lenia_values_stay_between_0_and_1() {
+        // On a uniform grid every cell's potential is its own value. A wide growth function keeps
+        // adding to it, and a narrow one centred away from it keeps taking it away.
+        let rules = [
+            ("R=5;T=10;b=1;m=0.15;s=1;kn=1;gn=1", 1.0),
+            ("R=5;T=10;b=1;m=0.15;s=0.015;kn=1;gn=1", 0.0),
+        ];
+        for (rule, limit) in rules {
+            let mut simulation = CpuSimulation::new(16, 16, rule.parse().unwrap());
+            simulation.write_cells(&[1; 256]);
+            simulation.write_values(&[0.5; 256]);
+            simulation.step(20);
+            assert_eq!(simulation.read_values(), vec![limit; 256], "{rule}");
+            assert_eq!(
+                simulation.read_cells(),
+                vec![(limit > 0.0) as u32; 256],
+                "{rule}"
+            );
+        }
+    }
+
+    #[test]
+    fn rule_30_grows_its_triangle_a_row_at_a_time() {
+        let mut simulation = CpuSimulation::new(9, 4, Rule::Elementary(30));
+        simulation.write_cell(4, 0, 1);
+        // The live cells of each row of the history, from the bottom
+        let history = |rows: [&[u32]; 4]| {
+            let mut cells: Vec<_> = (0..4)
+                .flat_map(|y| rows[y as usize].iter().map(move |&x| (x, y)))
+                .collect();
+            cells.sort();
+            cells
+        };
+
+        simulation.step(3);
+        assert_eq!(
+            live_cells(&simulation),
+            history([&[4], &[3, 4, 5], &[2, 3, 6], &[1, 2, 4, 5, 6, 7]])
+        );
+
+        // Once the history is full, the next row replaces the oldest
+        simulation.step(1);
+        assert_eq!(
+            live_cells(&simulation),
+            history([&[0, 1, 4, 8], &[3, 4, 5], &[2, 3, 6], &[1, 2, 4, 5, 6, 7]])
+        );
+    }
+}
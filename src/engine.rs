@@ -0,0 +1,340 @@
+// A common interface over the GPU and CPU simulations, so that headless callers can fall back
+// to the CPU when there's no adapter, and so the two can be checked against each other.
+// Reading cells back from the GPU blocks, so this is only available natively.
+
+use crate::cpu::CpuSimulation;
+use crate::rule::Rule;
+use crate::simulation::Simulation;
+use std::fmt;
+#[allow(unused_imports)]
+use tracing::{error, info, warn};
+
+pub trait Engine {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    fn depth(&self) -> u32;
+    fn generation(&self) -> u32;
+    fn step(&mut self, n: u32);
+    fn read_cells(&self) -> Vec<u32>;
+    fn write_cells(&mut self, cells: &[u32]);
+    fn read_values(&self) -> Vec<f32>;
+}
+
+impl Engine for Simulation {
+    fn width(&self) -> u32 {
+        self.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.height()
+    }
+
+    fn depth(&self) -> u32 {
+        self.depth()
+    }
+
+    fn generation(&self) -> u32 {
+        self.generation()
+    }
+
+    fn step(&mut self, n: u32) {
+        self.step(n)
+    }
+
+    fn read_cells(&self) -> Vec<u32> {
+        self.read_cells()
+    }
+
+    fn write_cells(&mut self, cells: &[u32]) {
+        self.write_cells(cells)
+    }
+
+    fn read_values(&self) -> Vec<f32> {
+        self.read_values()
+    }
+}
+
+impl Engine for CpuSimulation {
+    fn width(&self) -> u32 {
+        self.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.height()
+    }
+
+    fn depth(&self) -> u32 {
+        self.depth()
+    }
+
+    fn generation(&self) -> u32 {
+        self.generation()
+    }
+
+    fn step(&mut self, n: u32) {
+        self.step(n)
+    }
+
+    fn read_cells(&self) -> Vec<u32> {
+        self.read_cells()
+    }
+
+    fn write_cells(&mut self, cells: &[u32]) {
+        self.write_cells(cells)
+    }
+
+    fn read_values(&self) -> Vec<f32> {
+        self.read_values()
+    }
+}
+
+// Create a GPU simulation, or a CPU simulation if no GPU device is available
+pub async fn create_engine(width: u32, height: u32, rule: Rule) -> Box<dyn Engine> {
+    match Simulation::new(width, height, rule).await {
+        Ok(simulation) => Box::new(simulation),
+        Err(e) => {
+            warn!("Falling back to the CPU simulation: {}", e);
+            Box::new(CpuSimulation::new(width, height, rule))
+        }
+    }
+}
+
+// How far apart the values of a continuous rule's cells can be before the engines are said to
+// disagree. The GPU adds up each cell's potential in a different order, so the values are
+// rounded differently.
+pub const VALUE_TOLERANCE: f32 = 1e-4;
+
+// The first cell where two engines disagree
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub generation: u32,
+    pub x: u32,
+    pub y: u32,
+    // The layer, which is 0 unless the grid is 3D
+    pub z: u32,
+    pub difference: Difference,
+}
+
+// What two engines disagree about at a cell
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difference {
+    State { expected: u32, actual: u32 },
+    // The values of a continuous rule's cells, more than VALUE_TOLERANCE apart
+    Value { expected: f32, actual: f32 },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "engines diverged at generation {}, cell ({}, {})",
+            self.generation, self.x, self.y
+        )?;
+        if self.z > 0 {
+            write!(f, " in layer {}", self.z)?;
+        }
+        match self.difference {
+            Difference::State { expected, actual } => {
+                write!(f, ": expected {}, got {}", expected, actual)
+            }
+            Difference::Value { expected, actual } => write!(
+                f,
+                ": expected a value of {}, got {}, a difference of {}",
+                expected,
+                actual,
+                (actual - expected).abs()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+// Step two engines in lockstep for a number of generations, comparing every generation.
+// The engines should start from the same cell state.
+pub fn verify(
+    expected: &mut dyn Engine,
+    actual: &mut dyn Engine,
+    generations: u32,
+) -> Result<(), Divergence> {
+    assert_eq!(
+        (expected.width(), expected.height(), expected.depth()),
+        (actual.width(), actual.height(), actual.depth()),
+        "engines have different grid sizes"
+    );
+
+    compare(expected, actual)?;
+    for _ in 0..generations {
+        expected.step(1);
+        actual.step(1);
+        compare(expected, actual)?;
+    }
+    Ok(())
+}
+
+fn compare(expected: &dyn Engine, actual: &dyn Engine) -> Result<(), Divergence> {
+    let (width, height) = (expected.width(), expected.height());
+    let divergence = |i: usize, difference| Divergence {
+        generation: expected.generation(),
+        x: i as u32 % width,
+        y: i as u32 / width % height,
+        z: i as u32 / (width * height),
+        difference,
+    };
+
+    // The states of a continuous rule's cells follow from their values, so a value rounded to
+    // just above 0 by one engine and to 0 by the other would make the states differ. The values
+    // are compared instead.
+    let expected_values = expected.read_values();
+    if !expected_values.is_empty() {
+        let actual_values = actual.read_values();
+        let position = expected_values
+            .iter()
+            .zip(&actual_values)
+            .position(|(e, a)| (e - a).abs() > VALUE_TOLERANCE);
+        return match position {
+            Some(i) => Err(divergence(
+                i,
+                Difference::Value {
+                    expected: expected_values[i],
+                    actual: actual_values[i],
+                },
+            )),
+            None => Ok(()),
+        };
+    }
+
+    let expected_cells = expected.read_cells();
+    let actual_cells = actual.read_cells();
+    match expected_cells
+        .iter()
+        .zip(&actual_cells)
+        .position(|(e, a)| e != a)
+    {
+        Some(i) => Err(divergence(
+            i,
+            Difference::State {
+                expected: expected_cells[i],
+                actual: actual_cells[i],
+            },
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_life(width: u32, height: u32) -> CpuSimulation {
+        let mut simulation = CpuSimulation::new(width, height, "B3/S23".parse().unwrap());
+        simulation.randomise();
+        simulation
+    }
+
+    fn copy_of(simulation: &CpuSimulation) -> CpuSimulation {
+        let (width, height) = (simulation.width(), simulation.height());
+        let mut copy = CpuSimulation::new(width, height, simulation.rule());
+        copy.resize_3d(width, height, simulation.depth());
+        copy.write_cells(&simulation.read_cells());
+        if simulation.rule().is_continuous() {
+            copy.write_values(&simulation.read_values());
+        }
+        copy
+    }
+
+    // An empty Lenia grid, and a copy with one cell at (3, 5) given a value
+    fn lenia_with_value(value: f32) -> (CpuSimulation, CpuSimulation) {
+        let rule = "R=5;T=10;b=1;m=0.15;s=0.015;kn=1;gn=1".parse().unwrap();
+        let expected = CpuSimulation::new(16, 12, rule);
+        let mut actual = copy_of(&expected);
+        actual.write_cell(3, 5, 1);
+        let mut values = actual.read_values();
+        values[5 * 16 + 3] = value;
+        actual.write_values(&values);
+        (expected, actual)
+    }
+
+    #[test]
+    fn identical_engines_match() {
+        let mut expected = random_life(16, 12);
+        let mut actual = copy_of(&expected);
+        assert_eq!(verify(&mut expected, &mut actual, 20), Ok(()));
+    }
+
+    #[test]
+    fn changed_cell_is_reported() {
+        let mut expected = random_life(16, 12);
+        let mut actual = copy_of(&expected);
+        let state = expected.read_cells()[5 * 16 + 3];
+        actual.write_cell(3, 5, 1 - state);
+
+        assert_eq!(
+            verify(&mut expected, &mut actual, 20),
+            Err(Divergence {
+                generation: 0,
+                x: 3,
+                y: 5,
+                z: 0,
+                difference: Difference::State {
+                    expected: state,
+                    actual: 1 - state,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn different_rules_diverge_in_a_later_generation() {
+        // A lone row of three cells is a blinker under Life, but grows under Seeds
+        let mut expected = CpuSimulation::new(8, 8, "B3/S23".parse().unwrap());
+        let mut actual = CpuSimulation::new(8, 8, "B2/S".parse().unwrap());
+        for x in 2..5 {
+            expected.write_cell(x, 3, 1);
+            actual.write_cell(x, 3, 1);
+        }
+
+        let divergence = verify(&mut expected, &mut actual, 4).unwrap_err();
+        assert_eq!(divergence.generation, 1);
+    }
+
+    #[test]
+    fn divergence_reports_the_layer_of_3d_grids() {
+        let mut expected = CpuSimulation::new(8, 8, "4555".parse().unwrap());
+        expected.resize_3d(8, 6, 5);
+        let mut actual = copy_of(&expected);
+        let mut cells = actual.read_cells();
+        cells[(4 * 6 + 3) * 8 + 2] = 1;
+        actual.write_cells(&cells);
+
+        let divergence = verify(&mut expected, &mut actual, 1).unwrap_err();
+        assert_eq!((divergence.x, divergence.y, divergence.z), (2, 3, 4));
+    }
+
+    #[test]
+    fn close_values_match_even_if_their_states_differ() {
+        // The cell is alive in one engine and dead in the other, but only just
+        let (mut expected, mut actual) = lenia_with_value(VALUE_TOLERANCE / 2.0);
+        assert_eq!(verify(&mut expected, &mut actual, 5), Ok(()));
+    }
+
+    #[test]
+    fn value_differences_are_reported() {
+        let (mut expected, mut actual) = lenia_with_value(0.5);
+        let divergence = verify(&mut expected, &mut actual, 5).unwrap_err();
+        assert_eq!(
+            divergence,
+            Divergence {
+                generation: 0,
+                x: 3,
+                y: 5,
+                z: 0,
+                difference: Difference::Value {
+                    expected: 0.0,
+                    actual: 0.5,
+                },
+            }
+        );
+        assert!(divergence.to_string().ends_with("a difference of 0.5"));
+    }
+}
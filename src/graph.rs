@@ -0,0 +1,219 @@
+// A plot of population against generation, drawn over the grid.
+// The vertices are rebuilt on the CPU from the simulation's population history whenever a new
+// count arrives, and drawn as a triangle list in clip space: a translucent background panel,
+// the area under the curve, and a brighter line along its top.
+
+use crate::population::{PopulationSample, POPULATION_HISTORY_LENGTH};
+use std::borrow::Cow;
+
+// Where the graph is drawn, in clip space (bottom left corner)
+const PANEL_MIN: [f32; 2] = [-0.95, -0.95];
+const PANEL_MAX: [f32; 2] = [-0.35, -0.6];
+// Space between the panel's edge and the plot, in clip space
+const PANEL_PADDING: f32 = 0.02;
+// Thickness of the line along the top of the plot, in clip space
+const LINE_THICKNESS: f32 = 0.006;
+
+const PANEL_COLOUR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const AREA_COLOUR: [f32; 4] = [0.2, 0.8, 0.4, 0.35];
+const LINE_COLOUR: [f32; 4] = [0.4, 1.0, 0.6, 1.0];
+
+const VERTICES_PER_QUAD: usize = 6;
+// The panel, plus an area quad and a line quad between each pair of samples
+const MAX_VERTICES: usize =
+    VERTICES_PER_QUAD + (POPULATION_HISTORY_LENGTH - 1) * 2 * VERTICES_PER_QUAD;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GraphVertex {
+    position: [f32; 2],
+    colour: [f32; 4],
+}
+
+impl GraphVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GraphVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+pub(crate) struct PopulationGraph {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+    visible: bool,
+    // The history the vertices were built from, so they're only rebuilt when it changes
+    plotted: Option<(usize, PopulationSample)>,
+}
+
+impl PopulationGraph {
+    pub(crate) fn new(device: &wgpu::Device, texture_format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Graph Shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("graph.wgsl"))),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Graph Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Graph Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[GraphVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: texture_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Graph Vertex Buffer"),
+            size: (MAX_VERTICES * std::mem::size_of::<GraphVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            render_pipeline,
+            vertex_buffer,
+            num_vertices: 0,
+            visible: false,
+            plotted: None,
+        }
+    }
+
+    pub(crate) fn visible(&self) -> bool {
+        self.visible
+    }
+
+    pub(crate) fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    // Rebuild the vertices if the population history has changed since the last update
+    pub(crate) fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        history: impl ExactSizeIterator<Item = PopulationSample> + Clone,
+    ) {
+        let plotted = history.clone().last().map(|latest| (history.len(), latest));
+        if !self.visible || plotted == self.plotted {
+            return;
+        }
+        self.plotted = plotted;
+
+        let vertices = build_vertices(history);
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        self.num_vertices = vertices.len() as u32;
+    }
+
+    // Record drawing the graph into a render pass that has already drawn the grid
+    pub(crate) fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if !self.visible || self.num_vertices == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
+
+fn build_vertices(
+    history: impl ExactSizeIterator<Item = PopulationSample> + Clone,
+) -> Vec<GraphVertex> {
+    let mut vertices = Vec::with_capacity(MAX_VERTICES);
+    push_quad(&mut vertices, PANEL_MIN, PANEL_MAX, PANEL_COLOUR);
+
+    // The plot fills the panel's width once the history is full, and scrolls from then on.
+    // The population axis starts at zero and is scaled to the largest population shown.
+    let plot_min = [PANEL_MIN[0] + PANEL_PADDING, PANEL_MIN[1] + PANEL_PADDING];
+    let plot_max = [PANEL_MAX[0] - PANEL_PADDING, PANEL_MAX[1] - PANEL_PADDING];
+    let max_population = history
+        .clone()
+        .map(|sample| sample.population)
+        .max()
+        .unwrap_or(0)
+        .max(1) as f32;
+    let x_step = (plot_max[0] - plot_min[0]) / (POPULATION_HISTORY_LENGTH - 1) as f32;
+
+    let points: Vec<[f32; 2]> = history
+        .enumerate()
+        .map(|(i, sample)| {
+            let y = sample.population as f32 / max_population;
+            [
+                plot_min[0] + i as f32 * x_step,
+                plot_min[1] + y * (plot_max[1] - plot_min[1]),
+            ]
+        })
+        .collect();
+
+    for pair in points.windows(2) {
+        let (left, right) = (pair[0], pair[1]);
+        let area_bottom = [plot_min[1], plot_min[1]];
+        push_band(&mut vertices, left, right, area_bottom, AREA_COLOUR);
+        let line_bottom = [left[1] - LINE_THICKNESS, right[1] - LINE_THICKNESS];
+        push_band(&mut vertices, left, right, line_bottom, LINE_COLOUR);
+    }
+    vertices
+}
+
+fn push_quad(vertices: &mut Vec<GraphVertex>, min: [f32; 2], max: [f32; 2], colour: [f32; 4]) {
+    let corners = [
+        [min[0], min[1]],
+        [max[0], min[1]],
+        [max[0], max[1]],
+        [min[0], min[1]],
+        [max[0], max[1]],
+        [min[0], max[1]],
+    ];
+    vertices.extend(
+        corners
+            .into_iter()
+            .map(|position| GraphVertex { position, colour }),
+    );
+}
+
+// A band below the segment from left to right, with its bottom edge at the given heights
+fn push_band(
+    vertices: &mut Vec<GraphVertex>,
+    left: [f32; 2],
+    right: [f32; 2],
+    bottom: [f32; 2],
+    colour: [f32; 4],
+) {
+    let corners = [
+        [left[0], bottom[0]],
+        [right[0], bottom[1]],
+        right,
+        [left[0], bottom[0]],
+        right,
+        left,
+    ];
+    vertices.extend(
+        corners
+            .into_iter()
+            .map(|position| GraphVertex { position, colour }),
+    );
+}
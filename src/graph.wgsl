@@ -0,0 +1,25 @@
+// Population graph overlay.
+// The vertices are already in clip space, so this just passes them through with their colour.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) colour: vec4<f32>,
+};
+
+@vertex
+fn vs_main(input: VertexInput) -> VertexOutput {
+    var output: VertexOutput;
+    output.clip_position = vec4<f32>(input.position, 0.0, 1.0);
+    output.colour = input.colour;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    return input.colour;
+}
@@ -0,0 +1,409 @@
+// Hensel notation for isotropic non-totalistic rules, e.g. B2-a/S12.
+//
+// A digit alone means any arrangement of that many live neighbours, as in B/S notation. Letters
+// after a digit narrow it down to the arrangements they name, up to rotation and reflection, and a
+// minus sign before the letters excludes them instead. 2a is an orthogonal neighbour next to a
+// diagonal one, so 2-a is any other pair of neighbours.
+//
+// An arrangement of live neighbours is an 8-bit mask with a bit for each neighbour in reading
+// order: NW, N, NE, W, E, SW, S, SE, where north is towards +y. The compute shader builds the same
+// mask to index the lookup table compiled from a rule.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::rule::RuleParseError;
+
+// The letters for each neighbour count, in Golly's canonical order. Counts above 4 use the
+// letters of the complementary count, e.g. 5c is 3c with the live and dead neighbours swapped.
+const LETTERS: [&str; 5] = ["", "ce", "ceaikn", "ceaiknjqry", "ceaiknjqrytwz"];
+
+// An arrangement of live neighbours for each letter, in the same order as LETTERS
+const ARRANGEMENTS: [&[u8]; 5] = [
+    &[0b0000_0000],
+    &[0b0000_0001, 0b0000_0010],
+    &[
+        0b0000_0101,
+        0b0000_1010,
+        0b0000_0011,
+        0b0001_1000,
+        0b0001_0001,
+        0b0010_0100,
+    ],
+    &[
+        0b0010_0101,
+        0b0001_1010,
+        0b0000_1011,
+        0b0000_0111,
+        0b0011_0010,
+        0b0000_1101,
+        0b0000_1110,
+        0b0010_0110,
+        0b0001_1001,
+        0b0011_0001,
+    ],
+    &[
+        0b1010_0101,
+        0b0101_1010,
+        0b0000_1111,
+        0b0001_1101,
+        0b0011_0011,
+        0b0010_0111,
+        0b0011_1010,
+        0b0011_0110,
+        0b0001_1011,
+        0b0011_0101,
+        0b0011_1001,
+        0b0010_1110,
+        0b0011_1100,
+    ],
+];
+
+// Where each neighbour moves to when the neighbourhood is rotated a quarter turn anticlockwise,
+// and when it's reflected left to right
+const ROTATION: [u32; 8] = [5, 3, 0, 6, 1, 7, 4, 2];
+const REFLECTION: [u32; 8] = [2, 1, 0, 4, 3, 7, 6, 5];
+
+const MAX_NEIGHBOURS: u32 = 8;
+// Totalistic rules may count more neighbours, up to the 12 around a triangle. Counts above 9 are
+// written as the letters a, b and c.
+const MAX_TOTALISTIC_NEIGHBOURS: u32 = 12;
+const COUNT_RADIX: u32 = MAX_TOTALISTIC_NEIGHBOURS + 1;
+
+// The letters that apply to a neighbour count. Counts 0 and 8 have none.
+fn letters(count: u32) -> &'static str {
+    LETTERS[count.min(MAX_NEIGHBOURS - count) as usize]
+}
+
+// A mask with a bit for each distinct arrangement of a neighbour count. Counts 0 and 8 have a
+// single arrangement, so they use bit 0.
+fn all_letters(count: u32) -> u16 {
+    (1 << letters(count).len().max(1)) - 1
+}
+
+fn arrangement(count: u32, letter: usize) -> u8 {
+    if count <= 4 {
+        ARRANGEMENTS[count as usize][letter]
+    } else {
+        !ARRANGEMENTS[(MAX_NEIGHBOURS - count) as usize][letter]
+    }
+}
+
+fn permute(neighbourhood: u8, permutation: &[u32; 8]) -> u8 {
+    (0..8)
+        .filter(|bit| neighbourhood & (1 << bit) != 0)
+        .fold(0, |permuted, bit| permuted | (1 << permutation[bit]))
+}
+
+// The 8 rotations and reflections of a neighbourhood. Some may be the same.
+fn symmetries(neighbourhood: u8) -> impl Iterator<Item = u8> {
+    (0..4)
+        .scan(neighbourhood, |rotated, _| {
+            let current = *rotated;
+            *rotated = permute(current, &ROTATION);
+            Some(current)
+        })
+        .flat_map(|rotated| [rotated, permute(rotated, &REFLECTION)])
+}
+
+// The index of the letter describing an arrangement of live neighbours
+fn letter_of(neighbourhood: u8) -> usize {
+    let count = neighbourhood.count_ones();
+    (0..letters(count).len())
+        .find(|&letter| symmetries(arrangement(count, letter)).any(|n| n == neighbourhood))
+        .unwrap_or(0)
+}
+
+// The arrangements of live neighbours that cause a birth, or let a cell survive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transitions {
+    // Bit i of letters[n] is set for n live neighbours arranged as the i-th letter for n. Counts
+    // above 8 have no letters, so they only use bit 0.
+    letters: [u16; COUNT_RADIX as usize],
+}
+
+impl Transitions {
+    // Any arrangement of each of the neighbour counts, as in B/S notation
+    pub const fn totalistic(counts: &[u32]) -> Self {
+        const ALL_LETTERS: [u16; COUNT_RADIX as usize] = [
+            0x1, 0x3, 0x3f, 0x3ff, 0x1fff, 0x3ff, 0x3f, 0x3, 0x1, 0x1, 0x1, 0x1, 0x1,
+        ];
+        let mut letters = [0; COUNT_RADIX as usize];
+        let mut i = 0;
+        while i < counts.len() {
+            letters[counts[i] as usize] = ALL_LETTERS[counts[i] as usize];
+            i += 1;
+        }
+        Self { letters }
+    }
+
+    // Parse neighbour counts from 0 to max_count without letters, for neighbourhoods that Hensel
+    // notation doesn't describe. Counts above 9 are a, b and c.
+    pub(crate) fn parse_totalistic(digits: &str, max_count: u32) -> Result<Self, RuleParseError> {
+        let mut counts = Vec::new();
+        for c in digits.chars() {
+            // Only neighbourhoods of more than 9 cells take the letters as counts
+            let count = c
+                .to_digit(max_count + 1)
+                .ok_or(RuleParseError::InvalidNeighbourCount(c, max_count))?;
+            if counts.contains(&count) {
+                return Err(RuleParseError::DuplicateNeighbourCount(c));
+            }
+            counts.push(count);
+        }
+        Ok(Self::totalistic(&counts))
+    }
+
+    // Whether a neighbourhood, as a mask of its live neighbours, is one of the arrangements
+    pub fn contains(&self, neighbourhood: u8) -> bool {
+        let count = neighbourhood.count_ones() as usize;
+        self.letters[count] & (1 << letter_of(neighbourhood)) != 0
+    }
+
+    // Whether any arrangement of a number of live neighbours is included
+    pub fn contains_count(&self, count: u32) -> bool {
+        self.letters
+            .get(count as usize)
+            .is_some_and(|&letters| letters != 0)
+    }
+}
+
+impl FromStr for Transitions {
+    type Err = RuleParseError;
+
+    // Accepts digits, each optionally followed by letters or a minus sign and letters, e.g. 2-a3
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut transitions = Self {
+            letters: [0; COUNT_RADIX as usize],
+        };
+        let mut seen = 0u32;
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            let count = c
+                .to_digit(10)
+                .filter(|&count| count <= MAX_NEIGHBOURS)
+                .ok_or(RuleParseError::InvalidNeighbourCount(c, MAX_NEIGHBOURS))?;
+            if seen & (1 << count) != 0 {
+                return Err(RuleParseError::DuplicateNeighbourCount(c));
+            }
+            seen |= 1 << count;
+
+            let exclude = chars.next_if_eq(&'-').is_some();
+            let mut named = 0;
+            while let Some(letter) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+                let index = letters(count)
+                    .find(letter.to_ascii_lowercase())
+                    .ok_or(RuleParseError::InvalidLetter(letter, count))?;
+                named |= 1 << index;
+            }
+            transitions.letters[count as usize] = if exclude {
+                all_letters(count) & !named
+            } else if named == 0 {
+                all_letters(count)
+            } else {
+                named
+            };
+        }
+        Ok(transitions)
+    }
+}
+
+// Each count is written with whichever of its letters or the letters it excludes is shorter
+impl fmt::Display for Transitions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for count in 0..=MAX_TOTALISTIC_NEIGHBOURS {
+            let included = self.letters[count as usize];
+            if included == 0 {
+                continue;
+            }
+            write!(f, "{}", char::from_digit(count, COUNT_RADIX).unwrap())?;
+            if count > MAX_NEIGHBOURS {
+                continue;
+            }
+            let excluded = all_letters(count) & !included;
+            if excluded == 0 {
+                continue;
+            }
+            let named = |mask: u16| -> String {
+                letters(count)
+                    .chars()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0)
+                    .map(|(_, letter)| letter)
+                    .collect()
+            };
+            if excluded.count_ones() < included.count_ones() {
+                write!(f, "-{}", named(excluded))?;
+            } else {
+                write!(f, "{}", named(included))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The neighbours in reading order, as (x, y) offsets with north towards +y
+    const OFFSETS: [(i32, i32); 8] = [
+        (-1, 1),
+        (0, 1),
+        (1, 1),
+        (-1, 0),
+        (1, 0),
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+    ];
+
+    fn neighbour_at(offset: (i32, i32)) -> u32 {
+        OFFSETS.iter().position(|&o| o == offset).unwrap() as u32
+    }
+
+    // The neighbourhoods with some live neighbours, given as (x, y) offsets
+    fn mask(offsets: &[(i32, i32)]) -> u8 {
+        offsets
+            .iter()
+            .fold(0, |mask, &offset| mask | 1 << neighbour_at(offset))
+    }
+
+    // Every arrangement of live neighbours described by a count and letter
+    fn class(count: u32, letter: usize) -> Vec<u8> {
+        let mut class: Vec<u8> = symmetries(arrangement(count, letter)).collect();
+        class.sort();
+        class.dedup();
+        class
+    }
+
+    fn sorted(mut masks: Vec<u8>) -> Vec<u8> {
+        masks.sort();
+        masks
+    }
+
+    #[test]
+    fn rotation_and_reflection_move_neighbours_geometrically() {
+        for (i, &(x, y)) in OFFSETS.iter().enumerate() {
+            assert_eq!(ROTATION[i], neighbour_at((-y, x)));
+            assert_eq!(REFLECTION[i], neighbour_at((-x, y)));
+        }
+    }
+
+    #[test]
+    fn classes_of_each_count_cover_every_arrangement_once() {
+        for count in 0..=MAX_NEIGHBOURS {
+            let mut seen = Vec::new();
+            for letter in 0..letters(count).len().max(1) {
+                let class = class(count, letter);
+                assert!(class.iter().all(|n| n.count_ones() == count));
+                assert!(class.iter().all(|n| letter_of(*n) == letter));
+                assert!(
+                    class.iter().all(|n| !seen.contains(n)),
+                    "{count} classes overlap"
+                );
+                seen.extend(class);
+            }
+            let arrangements = (0..=255u8).filter(|n| n.count_ones() == count).count();
+            assert_eq!(
+                seen.len(),
+                arrangements,
+                "{count} classes don't cover every arrangement"
+            );
+        }
+    }
+
+    #[test]
+    fn letters_name_the_expected_neighbourhoods() {
+        let (nw, n, ne, w, e, sw, s, se) = (
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+            (-1, 0),
+            (1, 0),
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+        );
+        let letter = |count, letter| letters(count).find(letter).unwrap();
+
+        let corners = sorted(vec![mask(&[nw]), mask(&[ne]), mask(&[sw]), mask(&[se])]);
+        assert_eq!(class(1, letter(1, 'c')), corners);
+        assert_eq!(
+            class(1, letter(1, 'e')),
+            sorted(vec![mask(&[n]), mask(&[w]), mask(&[e]), mask(&[s])])
+        );
+        assert_eq!(
+            class(2, letter(2, 'c')),
+            sorted(vec![
+                mask(&[nw, ne]),
+                mask(&[ne, se]),
+                mask(&[se, sw]),
+                mask(&[sw, nw])
+            ])
+        );
+        assert_eq!(
+            class(2, letter(2, 'e')),
+            sorted(vec![
+                mask(&[n, e]),
+                mask(&[e, s]),
+                mask(&[s, w]),
+                mask(&[w, n])
+            ])
+        );
+        assert_eq!(
+            class(2, letter(2, 'i')),
+            sorted(vec![mask(&[n, s]), mask(&[w, e])])
+        );
+        assert_eq!(
+            class(2, letter(2, 'n')),
+            sorted(vec![mask(&[nw, se]), mask(&[ne, sw])])
+        );
+        // An orthogonal neighbour next to a diagonal one, and a knight's move apart
+        assert!(class(2, letter(2, 'a')).contains(&mask(&[n, ne])));
+        assert_eq!(class(2, letter(2, 'a')).len(), 8);
+        assert!(class(2, letter(2, 'k')).contains(&mask(&[nw, e])));
+        assert_eq!(class(2, letter(2, 'k')).len(), 8);
+        assert_eq!(class(4, letter(4, 'c')), vec![mask(&[nw, ne, sw, se])]);
+        assert_eq!(class(4, letter(4, 'e')), vec![mask(&[n, w, e, s])]);
+        // Counts above 4 are the complements of those below
+        assert_eq!(
+            class(7, letter(7, 'c')),
+            sorted(corners.iter().map(|n| !n).collect())
+        );
+        assert_eq!(
+            class(6, letter(6, 'i')),
+            sorted(vec![!mask(&[n, s]), !mask(&[w, e])])
+        );
+    }
+
+    #[test]
+    fn every_letter_is_the_same_as_no_letters() {
+        for count in 0..=MAX_NEIGHBOURS {
+            let all: String = format!("{count}{}", letters(count));
+            assert_eq!(all.parse(), Ok(Transitions::totalistic(&[count])), "{all}");
+        }
+    }
+
+    #[test]
+    fn excluded_letters_are_the_complement() {
+        let included: Transitions = "2a".parse().unwrap();
+        let excluded: Transitions = "2-a".parse().unwrap();
+        for neighbourhood in (0..=255u8).filter(|n| n.count_ones() == 2) {
+            assert_ne!(
+                included.contains(neighbourhood),
+                excluded.contains(neighbourhood)
+            );
+        }
+    }
+
+    #[test]
+    fn shortest_form_is_displayed() {
+        let display = |s: &str| s.parse::<Transitions>().unwrap().to_string();
+        assert_eq!(display("23"), "23");
+        assert_eq!(display("2ceaikn"), "2");
+        assert_eq!(display("2ceakn"), "2-i");
+        assert_eq!(display("2-ceakn"), "2i");
+        assert_eq!(display("3-a4i"), "3-a4i");
+    }
+}
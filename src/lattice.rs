@@ -0,0 +1,223 @@
+// How the cells are arranged. Cells are stored in rows whatever the lattice, but the lattice
+// decides their shape on screen and which cell is under the cursor.
+//
+// Each cell is given a square slot in the grid, as vs_main lays them out. On the hexagonal
+// lattice, odd rows are shifted right by half a slot, so each cell touches two cells in the row
+// above, two in the row below and one on either side. The hexagons are stretched vertically to
+// tile the square slots, so the rows keep their spacing.
+//
+// On the triangular lattice, cells where x + y is even point up and the others point down. Each
+// triangle's base spans its own slot and half of the slots either side, so neighbours in a row
+// interlock. A triangle shares its base with the cell below it if it points up, or the cell above
+// it if it points down. The triangles are squashed vertically to fit the square slots.
+//
+// Rows of hexagons alternate in their shift, so where the grid wraps from top to bottom the
+// rows only line up if there's an even number of them. Likewise triangles alternate along rows
+// and columns, so an odd width or height would join two triangles pointing the same way.
+// Mirroring an edge, as the Klein bottle and cross-surface do, flips the alternation on the
+// other side, so those topologies aren't supported on either lattice.
+
+use crate::topology::Topology;
+
+// Cells are drawn at this fraction of their slot, leaving a gap between neighbours
+const CELL_INSET: f32 = 0.8;
+
+// Squashing the hexagons vertically by this much makes them regular
+const HEXAGON_SQUASH: f32 = 0.866_025_4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lattice {
+    Square = 0,
+    Hexagonal = 1,
+    Triangular = 2,
+}
+
+impl Lattice {
+    fn name(self) -> &'static str {
+        match self {
+            Lattice::Square => "square",
+            Lattice::Hexagonal => "hexagonal",
+            Lattice::Triangular => "triangular",
+        }
+    }
+
+    // Whether the cells line up across the edges the topology joins
+    pub(crate) fn supports_topology(self, topology: Topology) -> bool {
+        match self {
+            Lattice::Square => true,
+            Lattice::Hexagonal | Lattice::Triangular => {
+                !matches!(topology, Topology::KleinBottle | Topology::CrossSurface)
+            }
+        }
+    }
+
+    // Round the grid dimensions up to ones whose cells line up across wrapped edges
+    pub(crate) fn fit_grid_size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Lattice::Square => (width, height),
+            Lattice::Hexagonal => (width, height.next_multiple_of(2)),
+            Lattice::Triangular => (width.next_multiple_of(2), height.next_multiple_of(2)),
+        }
+    }
+
+    // Move the bottom left corner of a pattern in the grid so that it starts on an unshifted
+    // row of hexagons or an upward triangle, so that the pattern keeps its shape when it's
+    // exported and loaded again
+    pub(crate) fn align(self, x: i64, y: i64) -> (i64, i64) {
+        match self {
+            Lattice::Square => (x, y),
+            Lattice::Hexagonal => (x, y - y.rem_euclid(2)),
+            Lattice::Triangular => (x - (x + y).rem_euclid(2), y),
+        }
+    }
+
+    // Why the lattice can't be used with a topology or grid size, if it can't
+    pub(crate) fn check_grid(
+        self,
+        topology: Topology,
+        width: u32,
+        height: u32,
+    ) -> Result<(), String> {
+        let name = self.name();
+        if !self.supports_topology(topology) {
+            return Err(format!(
+                "The {name} lattice can't be used with the {topology} topology, as it mirrors \
+                 the grid's edges"
+            ));
+        }
+        let (fitted_width, fitted_height) = self.fit_grid_size(width, height);
+        if (fitted_width, fitted_height) != (width, height) {
+            return Err(format!(
+                "The {name} lattice needs a grid size such as {fitted_width}x{fitted_height}, so \
+                 that its cells line up where the grid wraps"
+            ));
+        }
+        Ok(())
+    }
+
+    // The triangles of a cell's shape, in a slot spanning -1..1 on both axes. On the triangular
+    // lattice this is an upward triangle, which vs_main flips for the cells that point down.
+    pub(crate) fn cell_vertices(&self) -> Vec<[f32; 2]> {
+        // The shape is inset towards its centre
+        let centre = match self {
+            Lattice::Square | Lattice::Hexagonal => [0.0, 0.0],
+            Lattice::Triangular => [0.0, -1.0 / 3.0],
+        };
+        let triangles: &[[f32; 2]] = match self {
+            Lattice::Square => &[
+                [-1.0, -1.0],
+                [1.0, -1.0],
+                [1.0, 1.0],
+                [-1.0, -1.0],
+                [1.0, 1.0],
+                [-1.0, 1.0],
+            ],
+            // Pointed at the top and bottom, overlapping the slots above and below so that the
+            // shifted rows interlock
+            Lattice::Hexagonal => &[
+                [0.0, -4.0 / 3.0],
+                [1.0, -2.0 / 3.0],
+                [-1.0, -2.0 / 3.0],
+                [-1.0, -2.0 / 3.0],
+                [1.0, -2.0 / 3.0],
+                [1.0, 2.0 / 3.0],
+                [-1.0, -2.0 / 3.0],
+                [1.0, 2.0 / 3.0],
+                [-1.0, 2.0 / 3.0],
+                [-1.0, 2.0 / 3.0],
+                [1.0, 2.0 / 3.0],
+                [0.0, 4.0 / 3.0],
+            ],
+            Lattice::Triangular => &[[-2.0, -1.0], [2.0, -1.0], [0.0, 1.0]],
+        };
+        triangles
+            .iter()
+            .map(|[x, y]| {
+                [
+                    centre[0] + (x - centre[0]) * CELL_INSET,
+                    centre[1] + (y - centre[1]) * CELL_INSET,
+                ]
+            })
+            .collect()
+    }
+
+    // The cell at a position in grid space, where each slot is 1 across and the grid's bottom
+    // left corner is at the origin. Positions in the gaps between cells belong to the nearest
+    // cell, so that painting doesn't miss cells.
+    pub(crate) fn cell_at(
+        &self,
+        position: [f32; 2],
+        width: u32,
+        height: u32,
+    ) -> Option<(u32, u32)> {
+        let [x, y] = position;
+        let (grid_width, grid_height) = (width as f32, height as f32);
+        match self {
+            Lattice::Square => {
+                let (x, y) = (x.floor(), y.floor());
+                let inside = (0.0..grid_width).contains(&x) && (0.0..grid_height).contains(&y);
+                inside.then_some((x as u32, y as u32))
+            }
+            Lattice::Hexagonal => {
+                // Odd rows stick out half a slot past the right of the grid
+                let inside =
+                    (0.0..grid_width + 0.5).contains(&x) && (0.0..grid_height).contains(&y);
+                if !inside {
+                    return None;
+                }
+                // The cell whose centre is nearest, once the hexagons are made regular, in this
+                // row or the rows either side of it
+                let row = y.floor() as i64;
+                (row - 1..=row + 1)
+                    .filter(|&row| (0..height as i64).contains(&row))
+                    .map(|row| {
+                        let shift = Self::row_shift(row as u32);
+                        let column = (x - shift).floor().clamp(0.0, grid_width - 1.0);
+                        let dx = x - (column + 0.5 + shift);
+                        let dy = (y - (row as f32 + 0.5)) * HEXAGON_SQUASH;
+                        ((column as u32, row as u32), dx * dx + dy * dy)
+                    })
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .map(|(cell, _)| cell)
+            }
+            Lattice::Triangular => {
+                // The triangles at either end of a row stick out half a slot past the grid
+                let inside =
+                    (-0.5..grid_width + 0.5).contains(&x) && (0.0..grid_height).contains(&y);
+                if !inside {
+                    return None;
+                }
+                // Measured in half bases from the centre of the triangles' bases, a point is
+                // inside an upward triangle if it's within 1 - height of the centre, and inside a
+                // downward one if it's within height. The triangle the point is furthest inside
+                // is the one containing it.
+                let row = y.floor();
+                let height = y - row;
+                let column = x.floor() as i64;
+                (column - 1..=column + 1)
+                    .filter(|&column| (0..width as i64).contains(&column))
+                    .map(|column| {
+                        let distance = (x - (column as f32 + 0.5)).abs();
+                        let reach = if Self::points_up(column as u32, row as u32) {
+                            1.0 - height
+                        } else {
+                            height
+                        };
+                        ((column as u32, row as u32), reach - distance)
+                    })
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .map(|(cell, _)| cell)
+            }
+        }
+    }
+
+    // Whether a cell on the triangular lattice points up
+    fn points_up(x: u32, y: u32) -> bool {
+        (x + y).is_multiple_of(2)
+    }
+
+    // How far a row of hexagons is shifted right, in slots
+    fn row_shift(row: u32) -> f32 {
+        (row % 2) as f32 * 0.5
+    }
+}
@@ -1,4 +1,3 @@
-use instant::Instant;
 use std::borrow::Cow;
 #[allow(unused_imports)]
 use tracing::{error, info, warn};
@@ -7,6 +6,45 @@ use wasm_bindgen::prelude::*;
 use wgpu::util::DeviceExt;
 use winit::{event::*, event_loop::EventLoop, window::Window};
 
+mod camera;
+mod config;
+mod cpu;
+#[cfg(not(target_arch = "wasm32"))]
+mod engine;
+mod graph;
+mod hensel;
+mod lattice;
+mod palette;
+mod population;
+mod rle;
+mod rule;
+mod scheduler;
+mod simulation;
+mod style;
+mod topology;
+
+pub use config::Config;
+pub use cpu::CpuSimulation;
+#[cfg(not(target_arch = "wasm32"))]
+pub use engine::{create_engine, verify, Difference, Divergence, Engine};
+pub use hensel::Transitions;
+pub use lattice::Lattice;
+pub use palette::{Palette, PaletteError};
+pub use population::{PopulationSample, POPULATION_HISTORY_LENGTH};
+pub use rle::{Pattern, RleError};
+pub use rule::{
+    LargerThanLifeRule, LeniaFunction, LeniaRule, Life3DRule, LifeNeighbourhood, LifeRule,
+    Neighbourhood, Rule, RuleParseError, WIREWORLD_CONDUCTOR, WIREWORLD_EMPTY, WIREWORLD_HEAD,
+    WIREWORLD_TAIL,
+};
+pub use simulation::{Simulation, SimulationError};
+pub use topology::{Topology, TopologyParseError};
+use camera::{AspectMode, Camera, OrbitCamera};
+use config::clamp_grid_size;
+use graph::PopulationGraph;
+use scheduler::{pending_generations, Scheduler};
+use style::{ColourMode, Style};
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct Vertex {
@@ -27,108 +65,57 @@ impl Vertex {
     }
 }
 
-const VERTICES: &[Vertex] = &[
-    // Triangle 1
-    Vertex {
-        position: [-0.8, -0.8],
-    },
-    Vertex {
-        position: [0.8, -0.8],
-    },
-    Vertex {
-        position: [0.8, 0.8],
-    },
-    // Triangle 2
-    Vertex {
-        position: [-0.8, -0.8],
-    },
-    Vertex {
-        position: [0.8, 0.8],
-    },
-    Vertex {
-        position: [-0.8, 0.8],
-    },
-];
-const NUM_VERTICES: u32 = VERTICES.len() as u32;
-const NUM_SQUARE_INSTANCES: u32 = (GRID_SIZE * GRID_SIZE) as u32;
-
-const GRID_SIZE: usize = 64;
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct GridSizeUniform {
-    grid: [f32; 2],
-}
-
-impl GridSizeUniform {
-    fn new() -> Self {
-        Self {
-            grid: [GRID_SIZE as f32, GRID_SIZE as f32],
-        }
-    }
-}
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct CellState {
-    state_a: [u32; GRID_SIZE * GRID_SIZE],
-    state_b: [u32; GRID_SIZE * GRID_SIZE],
-}
-
-#[derive(PartialEq)]
-enum InitialCellState {
-    Random,
-    Empty,
-}
-
-impl CellState {
-    fn new(init: InitialCellState) -> Self {
-        use rand::Rng;
-
-        let mut grid_a = [0; GRID_SIZE * GRID_SIZE];
-        let grid_b = [0; GRID_SIZE * GRID_SIZE];
-        
-        if init == InitialCellState::Random {
-            let mut rng = rand::thread_rng();
 
-            for cell in grid_a.iter_mut().take(GRID_SIZE * GRID_SIZE) {
-                *cell = rng.gen_range(0..=1);
-            }
-        }
+// How much one line of mouse wheel scrolling zooms by
+const ZOOM_PER_LINE: f32 = 1.1;
+// Touchpads scroll in pixels rather than lines
+const PIXELS_PER_LINE: f32 = 50.0;
+// How far the arrow keys pan the view, in clip space
+const PAN_STEP: f32 = 0.1;
+// How far dragging the mouse turns the orbit camera, in radians per pixel
+const ORBIT_PER_PIXEL: f32 = 0.01;
+// How far the arrow keys turn the orbit camera, in radians
+const ORBIT_STEP: f32 = 0.1;
 
-        Self {
-            state_a: grid_a,
-            state_b: grid_b,
-        }
-    }
-}
-
-// We use two bind groups to enable the ping pong buffer pattern
-struct BindGroups {
-    group_a: wgpu::BindGroup,
-    group_b: wgpu::BindGroup,
-}
+// The cubes of 3D grids are drawn with a depth buffer, so that nearer cubes hide further ones
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+// The number of vertices in a cube's faces. Must match vs_cube in shader.wgsl.
+const CUBE_VERTICES: u32 = 36;
 
 struct State<'a> {
     window: &'a Window,
     window_size: winit::dpi::PhysicalSize<u32>,
     surface: wgpu::Surface<'a>,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     vertex_buffer: wgpu::Buffer,
-    bind_groups: BindGroups,
-    step: u32, // how many simulation steps have been run
+    num_vertices: u32,
+    lattice: Lattice, // the shape of the cells, which depends on the rule
+    simulation: Simulation,
+    pending_steps: u32, // how many generations to simulate before the next render
     paused: bool, // whether the simulation is paused
-    update_interval: u128, // how many milliseconds between simulation steps
+    scheduler: Scheduler,
     render_pipeline: wgpu::RenderPipeline,
-    compute_pipeline: wgpu::ComputePipeline,
-    cell_state_storage_buffer_state_a: wgpu::Buffer,
-    cell_state_storage_buffer_state_b: wgpu::Buffer,
+    grid_lines_pipeline: wgpu::RenderPipeline,
+    cube_pipeline: wgpu::RenderPipeline, // draws the cells of 3D grids
+    depth_view: wgpu::TextureView,
+    graph: PopulationGraph,
+    camera: Camera,
+    view_uniform_buffer: wgpu::Buffer,
+    orbit_camera: OrbitCamera, // looks around 3D grids
+    orbit_uniform_buffer: wgpu::Buffer,
+    view_bind_group: wgpu::BindGroup,
+    style: Style,
+    style_uniform_buffer: wgpu::Buffer,
+    panning: bool, // whether the view is being dragged with the middle mouse button
+    orbiting: bool, // whether the orbit camera is being dragged with the left mouse button
+    cursor_position: Option<winit::dpi::PhysicalPosition<f64>>,
+    brush: u32, // the cell state painted by the left mouse button
+    paint_value: Option<u32>, // the cell value being painted while a mouse button is held
+    last_painted_cell: Option<(u32, u32)>,
 }
 
 impl<'a> State<'a> {
-    async fn new(window: &'a Window) -> Self {
+    async fn new(window: &'a Window, startup_config: &Config) -> Result<Self, SimulationError> {
         let mut window_size = window.inner_size();
         window_size.width = window_size.width.max(1);
         window_size.height = window_size.height.max(1);
@@ -136,15 +123,9 @@ impl<'a> State<'a> {
         let instance = wgpu::Instance::default();
 
         let surface = instance.create_surface(window).unwrap();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                force_fallback_adapter: false,
-                // Request an adapter which can render to our surface
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .expect("Failed to find an appropriate adapter");
+        // Request an adapter which can render to our surface. The CPU simulation can't stand in
+        // for a missing adapter here, as drawing the grid needs one too.
+        let adapter = simulation::request_adapter(&instance, Some(&surface)).await?;
 
         let surface_capabilities = surface.get_capabilities(&adapter);
         let texture_format = surface_capabilities.formats[0];
@@ -154,19 +135,13 @@ impl<'a> State<'a> {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     required_features: wgpu::Features::empty(),
-                    // WebGL doesn't support all of wgpu's features, so if
-                    // we're building for the web we'll have to disable some.
-                    required_limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    },
+                    required_limits: simulation::required_limits(),
                     label: None,
                 },
                 None, // Trace path
             )
             .await
-            .expect("Failed to create device");
+            .map_err(SimulationError::RequestDevice)?;
 
 
         // Set the alpha mode to support a transparent window/canvas
@@ -197,135 +172,146 @@ impl<'a> State<'a> {
         
         surface.configure(&device, &config);
 
-        // Create the vertex buffer
+        // Create the simulation, which takes ownership of the device and queue
+        let mut simulation = Simulation::from_device(
+            device,
+            queue,
+            startup_config.grid_width,
+            startup_config.grid_height,
+            startup_config.rule,
+        );
+        if startup_config.rule.is_three_dimensional() {
+            simulation.resize_3d(
+                startup_config.grid_width,
+                startup_config.grid_height,
+                startup_config.grid_depth,
+            );
+        }
+        simulation.set_topology(startup_config.topology);
+        info!("Using rule {}", startup_config.rule);
+        match &startup_config.pattern {
+            Some(pattern) => simulation.load_pattern(pattern),
+            None => simulation.randomise(),
+        }
+        let device = simulation.device();
+
+        // Create the vertex buffer with the shape of a cell, which each instance draws
+        let lattice = simulation.rule().lattice();
+        let vertices: Vec<Vertex> = lattice
+            .cell_vertices()
+            .into_iter()
+            .map(|position| Vertex { position })
+            .collect();
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
+            contents: bytemuck::cast_slice(&vertices),
             usage: wgpu::BufferUsages::VERTEX,
         });
 
-        // Create grid size uniform buffer
-        let grid_size_uniform = GridSizeUniform::new();
-        let grid_size_uniform_buffer =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Grid Uniforms"),
-                contents: bytemuck::cast_slice(&[grid_size_uniform]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
+        // Load the shaders from disk
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
+        });
 
-        // Create cell state storage buffer
-        let cell_state = CellState::new(InitialCellState::Random);
-        let cell_state_storage_buffer_state_a =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Cell State Storage Buffer A"),
-                contents: bytemuck::cast_slice(&[cell_state.state_a]),
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            });
-        let cell_state_storage_buffer_state_b =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Cell State Storage Buffer B"),
-                contents: bytemuck::cast_slice(&[cell_state.state_b]),
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            });
+        // Create the view uniform buffer and its bind group
+        let camera = Camera::new(
+            (window_size.width, window_size.height),
+            (simulation.width(), simulation.height()),
+        );
+        let view_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("View Uniforms"),
+            contents: bytemuck::cast_slice(&[camera.view_uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::from_iter(
-                        wgpu::ShaderStages::VERTEX
-                            | wgpu::ShaderStages::FRAGMENT
-                            | wgpu::ShaderStages::COMPUTE,
-                    ),
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    // cell state input buffer (read only)
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::from_iter(
-                        wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
-                    ),
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    // cell state output buffer (read-write)
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::from_iter(wgpu::ShaderStages::COMPUTE),
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-            label: Some("bind_group_layout"),
+        // Create the orbit camera's uniform buffer, which shares the view's bind group
+        let orbit_camera = OrbitCamera::new();
+        let aspect_ratio = window_size.width as f32 / window_size.height as f32;
+        let orbit_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Orbit Uniforms"),
+            contents: bytemuck::cast_slice(&[orbit_camera.orbit_uniform(aspect_ratio)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let bind_groups = BindGroups {
-            group_a: device.create_bind_group(&wgpu::BindGroupDescriptor {
-                // bind group a:
-                // - cell state input: state a
-                // - cell state output: state b
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: grid_size_uniform_buffer.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: cell_state_storage_buffer_state_a.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: cell_state_storage_buffer_state_b.as_entire_binding(),
-                    },
-                ],
-                label: Some("Cell Renderer Bind Group A"),
-            }),
-            group_b: device.create_bind_group(&wgpu::BindGroupDescriptor {
-                // bind group b:
-                // - cell state input: state b
-                // - cell state output: state a
-                layout: &bind_group_layout,
+        // Create the style uniform buffer, which shares the view's bind group
+        let style = Style::new(
+            startup_config.trail_length,
+            &startup_config.palettes,
+            startup_config.palette.as_deref(),
+        );
+        let style_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Style Uniforms"),
+            contents: bytemuck::cast_slice(&[style.uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let view_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
-                    wgpu::BindGroupEntry {
+                    wgpu::BindGroupLayoutEntry {
+                        // view transform
                         binding: 0,
-                        resource: grid_size_uniform_buffer.as_entire_binding(),
+                        visibility: wgpu::ShaderStages::from_iter(wgpu::ShaderStages::VERTEX),
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    wgpu::BindGroupEntry {
+                    wgpu::BindGroupLayoutEntry {
+                        // colouring style
                         binding: 1,
-                        resource: cell_state_storage_buffer_state_b.as_entire_binding(),
+                        visibility: wgpu::ShaderStages::from_iter(
+                            wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ),
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    wgpu::BindGroupEntry {
+                    wgpu::BindGroupLayoutEntry {
+                        // orbit camera transform
                         binding: 2,
-                        resource: cell_state_storage_buffer_state_a.as_entire_binding(),
+                        visibility: wgpu::ShaderStages::from_iter(wgpu::ShaderStages::VERTEX),
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
                 ],
-                label: Some("Cell Renderer Bind Group B"),
-            }),
-        };
+                label: Some("view_bind_group_layout"),
+            });
 
-        // Load the shaders from disk
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
+        let view_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &view_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: view_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: style_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: orbit_uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("View Bind Group"),
         });
 
+        // The render pipeline shares the simulation's bind groups, so that it can read the
+        // current generation straight from the cell state buffers.
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout],
+            bind_group_layouts: &[simulation.bind_group_layout(), &view_bind_group_layout],
             push_constant_ranges: &[],
         });
 
@@ -353,31 +339,101 @@ impl<'a> State<'a> {
             multiview: None,
         });
 
-        // Create compute pipeline
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: None,
+        // Grid lines are drawn over the cells with the same bind groups, as a single quad
+        // covering the grid
+        let grid_lines_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Grid Lines Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_grid_lines",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_grid_lines",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: texture_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        // The cells of 3D grids are drawn as cubes, one instance per cell, which hide each other
+        // with a depth buffer. The faces facing away from the camera are hidden behind the
+        // others, so they're culled.
+        let cube_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Cube Pipeline"),
             layout: Some(&pipeline_layout),
-            module: &shader,
-            entry_point: "cs_main",
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_cube",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_cube",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: texture_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
         });
+        let depth_view = create_depth_view(device, window_size);
 
-        Self {
+        // The population graph is drawn over the grid by a second pipeline
+        let graph = PopulationGraph::new(device, texture_format);
+
+        Ok(Self {
             window,
             window_size,
             surface,
-            device,
-            queue,
             config,
             vertex_buffer,
-            bind_groups,
-            step: 0,
+            num_vertices: vertices.len() as u32,
+            lattice,
+            simulation,
+            pending_steps: 0,
             paused: false,
-            update_interval: 100,
+            scheduler: Scheduler::new(startup_config.generations_per_second),
             render_pipeline,
-            compute_pipeline,
-            cell_state_storage_buffer_state_a,
-            cell_state_storage_buffer_state_b,
-        }
+            grid_lines_pipeline,
+            cube_pipeline,
+            depth_view,
+            graph,
+            camera,
+            view_uniform_buffer,
+            orbit_camera,
+            orbit_uniform_buffer,
+            view_bind_group,
+            style,
+            style_uniform_buffer,
+            panning: false,
+            orbiting: false,
+            cursor_position: None,
+            brush: startup_config.rule.default_brush(),
+            paint_value: None,
+            last_painted_cell: None,
+        })
     }
 
     fn resize(&mut self, new_window_size: winit::dpi::PhysicalSize<u32>) {
@@ -387,15 +443,244 @@ impl<'a> State<'a> {
             self.window_size = new_window_size;
             self.config.width = new_window_size.width;
             self.config.height = new_window_size.height;
-            self.surface.configure(&self.device, &self.config);
+            self.surface.configure(self.simulation.device(), &self.config);
+            self.depth_view = create_depth_view(self.simulation.device(), new_window_size);
+            // Keep the cells square for the new window shape
+            self.update_viewport();
             // On macos the window needs to be redrawn manually after resizing
             self.window.request_redraw();
         }
     }
 
-    #[allow(dead_code, unused_variables)]
+    // Handle mouse painting and the camera controls.
+    // Left click/drag paints cells with the brush's state, right click/drag kills them, middle
+    // click/drag pans the view and the scroll wheel zooms. 3D grids can't be painted, so left
+    // click/drag turns the orbit camera instead.
     fn input(&mut self, event: &WindowEvent) -> bool {
-        false
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                if let (true, Some(previous)) = (self.panning, self.cursor_position) {
+                    let from = self.window_to_clip(previous);
+                    let to = self.window_to_clip(*position);
+                    self.camera.pan([to[0] - from[0], to[1] - from[1]]);
+                    self.update_view();
+                }
+                if let (true, Some(previous)) = (self.orbiting, self.cursor_position) {
+                    // Dragging turns the grid with the cursor, so the camera turns the other way
+                    let yaw = (previous.x - position.x) as f32 * ORBIT_PER_PIXEL;
+                    let pitch = (position.y - previous.y) as f32 * ORBIT_PER_PIXEL;
+                    self.orbit_camera.orbit(yaw, pitch);
+                    self.update_view();
+                }
+                self.cursor_position = Some(*position);
+                if self.paint_value.is_some() {
+                    self.paint_at_cursor();
+                }
+                true
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Middle,
+                ..
+            } => {
+                self.panning = *state == ElementState::Pressed;
+                true
+            }
+            WindowEvent::MouseInput { state, button, .. }
+                if self.simulation.rule().is_three_dimensional() =>
+            {
+                self.orbiting = *state == ElementState::Pressed && *button == MouseButton::Left;
+                true
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.paint_value = match (state, button) {
+                    (ElementState::Pressed, MouseButton::Left) => Some(self.brush),
+                    (ElementState::Pressed, MouseButton::Right) => Some(0),
+                    _ => None,
+                };
+                self.last_painted_cell = None;
+                if self.paint_value.is_some() {
+                    self.paint_at_cursor();
+                }
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                if self.simulation.rule().is_three_dimensional() {
+                    self.orbit_camera.zoom(ZOOM_PER_LINE.powf(lines));
+                    self.update_view();
+                    return true;
+                }
+                // Zoom in towards the cursor
+                let anchor = self
+                    .cursor_position
+                    .map_or([0.0, 0.0], |position| self.window_to_clip(position));
+                self.camera.zoom_at(ZOOM_PER_LINE.powf(lines), anchor);
+                self.update_view();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Move the view by a distance in clip space. 3D grids turn the orbit camera instead, as if
+    // the grid were dragged that far.
+    fn pan_view(&mut self, clip_delta: [f32; 2]) {
+        if self.simulation.rule().is_three_dimensional() {
+            let yaw = -clip_delta[0].signum() * ORBIT_STEP;
+            let pitch = -clip_delta[1].signum() * ORBIT_STEP;
+            self.orbit_camera.orbit(yaw, pitch);
+        } else {
+            self.camera.pan(clip_delta);
+        }
+        self.update_view();
+    }
+
+    // Reset the cameras to show the whole grid
+    fn fit_view(&mut self) {
+        self.camera.fit();
+        self.orbit_camera.fit();
+        self.update_view();
+    }
+
+    // Switch between letterboxing the grid and filling the window with it
+    fn toggle_aspect_mode(&mut self) {
+        let aspect_mode = match self.camera.aspect_mode() {
+            AspectMode::Letterbox => AspectMode::Fill,
+            AspectMode::Fill => AspectMode::Letterbox,
+        };
+        let window_size = (self.window_size.width, self.window_size.height);
+        self.camera.set_aspect_mode(window_size, aspect_mode);
+        self.update_view();
+    }
+
+    // Switch between colouring cells by position and by age
+    fn toggle_colour_mode(&mut self) {
+        let colour_mode = match self.style.colour_mode() {
+            ColourMode::Position => ColourMode::Age,
+            ColourMode::Age => ColourMode::Position,
+        };
+        self.style.set_colour_mode(colour_mode);
+        self.update_style();
+    }
+
+    fn next_palette(&mut self) {
+        self.style.next_palette();
+        info!("Using the {} palette", self.style.palette().name);
+        self.update_style();
+    }
+
+    fn toggle_grid_lines(&mut self) {
+        self.style.set_grid_lines(!self.style.grid_lines());
+        self.window.request_redraw();
+    }
+
+    // Choose the state the left mouse button paints with, e.g. a Wireworld conductor or
+    // electron head. States that the rule doesn't have are ignored.
+    fn set_brush(&mut self, state: u32) {
+        let rule = self.simulation.rule();
+        if state < rule.states() {
+            self.brush = state;
+            info!("Painting with state {} ({})", state, rule.state_name(state));
+        }
+    }
+
+    // Switch to the next topology, skipping those the rule's grid can't have
+    fn next_topology(&mut self) {
+        let rule = self.simulation.rule();
+        let mut topology = self.simulation.topology().next();
+        while !rule.supports_topology(topology) {
+            topology = topology.next();
+        }
+        self.simulation.set_topology(topology);
+    }
+
+    fn toggle_trails(&mut self) {
+        self.style.set_trails(!self.style.trails());
+        self.update_style();
+    }
+
+    fn update_style(&mut self) {
+        self.simulation.queue().write_buffer(
+            &self.style_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.style.uniform()]),
+        );
+        self.window.request_redraw();
+    }
+
+    fn toggle_graph(&mut self) {
+        self.graph.set_visible(!self.graph.visible());
+        self.window.request_redraw();
+    }
+
+    // Recalculate the camera's viewport after the window or grid has been resized
+    fn update_viewport(&mut self) {
+        self.camera.set_viewport(
+            (self.window_size.width, self.window_size.height),
+            (self.simulation.width(), self.simulation.height()),
+        );
+        self.update_view();
+    }
+
+    // Upload the cameras' view transforms and redraw with them
+    fn update_view(&mut self) {
+        self.simulation.queue().write_buffer(
+            &self.view_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera.view_uniform()]),
+        );
+        let aspect_ratio = self.window_size.width as f32 / self.window_size.height as f32;
+        self.simulation.queue().write_buffer(
+            &self.orbit_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.orbit_camera.orbit_uniform(aspect_ratio)]),
+        );
+        self.window.request_redraw();
+    }
+
+    fn window_to_clip(&self, position: winit::dpi::PhysicalPosition<f64>) -> [f32; 2] {
+        [
+            (position.x / self.window_size.width as f64 * 2.0 - 1.0) as f32,
+            (1.0 - position.y / self.window_size.height as f64 * 2.0) as f32,
+        ]
+    }
+
+    // Convert a position in the window to the cell under it.
+    // This mirrors vs_main: each cell is given a 2/grid sized slot in world space, which the
+    // lattice shapes and offsets. The camera then maps world space to clip space.
+    fn window_to_cell(&self, position: winit::dpi::PhysicalPosition<f64>) -> Option<(u32, u32)> {
+        let world = self.camera.clip_to_world(self.window_to_clip(position));
+
+        let grid_width = self.simulation.width();
+        let grid_height = self.simulation.height();
+        let x = (world[0] + 1.0) / 2.0 * grid_width as f32;
+        let y = (world[1] + 1.0) / 2.0 * grid_height as f32;
+
+        self.lattice.cell_at([x, y], grid_width, grid_height)
+    }
+
+    fn paint_at_cursor(&mut self) {
+        let (Some(value), Some(position)) = (self.paint_value, self.cursor_position) else {
+            return;
+        };
+        let Some(cell) = self.window_to_cell(position) else {
+            self.last_painted_cell = None;
+            return;
+        };
+
+        // Fill in the cells between cursor events so that fast drags draw unbroken lines. The
+        // rows are drawn in a different order from how they're stored for some rules.
+        let from = self.last_painted_cell.unwrap_or(cell);
+        for (x, y) in cell_line(from, cell) {
+            self.simulation.write_cell(x, self.simulation.stored_row(y), value);
+        }
+        self.last_painted_cell = Some(cell);
+
+        self.window.request_redraw();
     }
 
     fn update(&mut self) {
@@ -410,7 +695,8 @@ impl<'a> State<'a> {
             .create_view(&wgpu::TextureViewDescriptor::default());
 
         let mut encoder = self
-            .device
+            .simulation
+            .device()
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
@@ -419,31 +705,57 @@ impl<'a> State<'a> {
         // the render pass to immediately use the latest results from the compute pass.
 
         // Compute Pass
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Compute Pass"),
-                timestamp_writes: None,
-            });
-            compute_pass.set_pipeline(&self.compute_pipeline);
+        // The simulation advances its generation after each compute pass so that the output
+        // buffer of the last compute pass becomes the input buffer for the render pipeline.
+        // If no generations are pending, the current generation is drawn without stepping.
+        self.simulation.encode_steps(&mut encoder, self.pending_steps);
+        self.pending_steps = 0;
 
-            let bind_group = if self.step % 2 == 0 {
-                &self.bind_groups.group_a
-            } else {
-                &self.bind_groups.group_b
-            };
+        self.graph
+            .update(self.simulation.queue(), self.simulation.population_history());
 
-            compute_pass.set_bind_group(0, bind_group, &[]);
-            const WORKGROUP_SIZE: usize = 8;
-            let workgroup_count = (GRID_SIZE as f32 / WORKGROUP_SIZE as f32).ceil() as u32;
+        // Render Pass
+        let background = self.style.palette().background;
+        let background = wgpu::LoadOp::Clear(wgpu::Color {
+            r: background[0] as f64,
+            g: background[1] as f64,
+            b: background[2] as f64,
+            a: background[3] as f64,
+        });
+        let num_cells = self.simulation.num_cells();
+        let three_dimensional = self.simulation.rule().is_three_dimensional();
 
-            compute_pass.dispatch_workgroups(workgroup_count, workgroup_count, 1);
-        }
+        // The cubes of 3D grids are drawn in a pass of their own, as only they use the depth
+        // buffer
+        if three_dimensional {
+            let mut cube_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Cube Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: background,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
 
-        // We increment the step count between the compute pass and render pass so that the
-        // output buffer of the compute pipeline becomes the input buffer for the render pipeline.
-        self.step += 1;
+            cube_pass.set_pipeline(&self.cube_pipeline);
+            cube_pass.set_bind_group(0, self.simulation.bind_group(), &[]);
+            cube_pass.set_bind_group(1, &self.view_bind_group, &[]);
+            cube_pass.draw(0..CUBE_VERTICES, 0..num_cells);
+        }
 
-        // Render Pass
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
@@ -451,12 +763,8 @@ impl<'a> State<'a> {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 0.5,
-                        }),
+                        // Keep the cubes if they've been drawn
+                        load: if three_dimensional { wgpu::LoadOp::Load } else { background },
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -465,48 +773,153 @@ impl<'a> State<'a> {
                 occlusion_query_set: None,
             });
 
-            let bind_group = if self.step % 2 == 0 {
-                &self.bind_groups.group_a
-            } else {
-                &self.bind_groups.group_b
-            };
+            if !three_dimensional {
+                render_pass.set_pipeline(&self.render_pipeline);
+                render_pass.set_bind_group(0, self.simulation.bind_group(), &[]);
+                render_pass.set_bind_group(1, &self.view_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                render_pass.draw(0..self.num_vertices, 0..num_cells);
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.draw(0..NUM_VERTICES, 0..NUM_SQUARE_INSTANCES);
+                // Grid lines follow the square slots, which only line up with square cells
+                if self.style.grid_lines() && self.lattice == Lattice::Square {
+                    render_pass.set_pipeline(&self.grid_lines_pipeline);
+                    render_pass.draw(0..6, 0..1);
+                }
+            }
+
+            // Draw the population graph on top of the grid
+            self.graph.draw(&mut render_pass);
         }
 
         // submit command buffers for execution
-        self.queue.submit(Some(encoder.finish()));
+        self.simulation.queue().submit(Some(encoder.finish()));
+        self.simulation.submitted();
         output.present();
 
         Ok(())
     }
 
-    fn reset_cell_state(&mut self, init: InitialCellState) {
-        // Reset the step counter
-        self.step = 0;
-
-        // Initialise new cell state
-        let new_cell_state = CellState::new(init);
+    // Export the current generation to an RLE file in the working directory
+    fn export_rle(&self) {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                warn!("Exporting RLE patterns isn't supported on the web");
+            } else {
+                let simulation = &self.simulation;
+                if simulation.rule().is_three_dimensional() {
+                    warn!("Exporting RLE patterns isn't supported for 3D rules");
+                    return;
+                }
+                let pattern = Pattern::from_cells(
+                    &simulation.read_cells(),
+                    simulation.width(),
+                    simulation.height(),
+                    Some(simulation.rule()),
+                );
+                let generation = simulation.generation();
+                let path = format!("life-wgpu-{}.rle", generation);
+                match std::fs::write(&path, pattern.to_rle()) {
+                    Ok(()) => info!("Exported generation {} to {}", generation, path),
+                    Err(e) => error!("Failed to export generation to {}: {}", path, e),
+                }
+            }
+        }
+    }
 
-        // Write the new cell states into the buffers
-        self.queue.write_buffer(
-            &self.cell_state_storage_buffer_state_a,
-            0,
-            bytemuck::cast_slice(&[new_cell_state.state_a]),
-        );
-        self.queue.write_buffer(
-            &self.cell_state_storage_buffer_state_b,
-            0,
-            bytemuck::cast_slice(&[new_cell_state.state_b]),
+    // Multiply the simulation speed by a factor
+    fn change_speed(&mut self, factor: f64) {
+        let generations_per_second = self.scheduler.generations_per_second() * factor;
+        self.scheduler.set_generations_per_second(generations_per_second);
+        info!(
+            "Simulating {:.1} generations per second",
+            self.scheduler.generations_per_second()
         );
     }
+
+    // Change the grid dimensions. The depth is ignored unless the rule is 3D. The new grid is
+    // randomised since the old cell state doesn't map onto it.
+    fn resize_grid(&mut self, width: u32, height: u32, depth: u32) {
+        let rule = self.simulation.rule();
+        let Some((width, height, depth)) = clamp_grid_size(rule, width, height, depth) else {
+            warn!("A {}x{}x{} grid would have too many cells", width, height, depth);
+            return;
+        };
+        let simulation = &self.simulation;
+        if (width, height, depth) == (simulation.width(), simulation.height(), simulation.depth())
+        {
+            return;
+        }
+
+        if rule.is_three_dimensional() {
+            info!("Resizing grid to {}x{}x{}", width, height, depth);
+        } else {
+            info!("Resizing grid to {}x{}", width, height);
+        }
+        self.simulation.resize_3d(width, height, depth);
+        self.simulation.randomise();
+        self.pending_steps = 0;
+        self.update_viewport();
+    }
+}
+
+// The depth buffer that 3D grids' cubes are drawn with, which must match the window's size
+fn create_depth_view(
+    device: &wgpu::Device,
+    window_size: winit::dpi::PhysicalSize<u32>,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Depth Texture"),
+        size: wgpu::Extent3d {
+            width: window_size.width,
+            height: window_size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// The cells on the line between two cells, inclusive (Bresenham's line algorithm)
+fn cell_line(from: (u32, u32), to: (u32, u32)) -> Vec<(u32, u32)> {
+    let (mut x, mut y) = (from.0 as i64, from.1 as i64);
+    let (to_x, to_y) = (to.0 as i64, to.1 as i64);
+    let dx = (to_x - x).abs();
+    let dy = -(to_y - y).abs();
+    let step_x = if x < to_x { 1 } else { -1 };
+    let step_y = if y < to_y { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    let mut cells = vec![(x as u32, y as u32)];
+    while (x, y) != (to_x, to_y) {
+        let error2 = 2 * error;
+        if error2 >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if error2 <= dx {
+            error += dx;
+            y += step_y;
+        }
+        cells.push((x as u32, y as u32));
+    }
+    cells
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
 pub async fn run() {
+    if let Err(e) = run_with_config(Config::default()).await {
+        error!("Failed to start: {}", e);
+    }
+}
+
+// Open a window and run the simulation in it until the window is closed. This needs an adapter
+// to draw with, so unlike the headless engine there's no CPU fallback if none is found.
+pub async fn run_with_config(startup_config: Config) -> Result<(), SimulationError> {
     // Set up logging
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
@@ -544,9 +957,7 @@ pub async fn run() {
     }
     let window = builder.build(&event_loop).expect("Failed to build window");
 
-    let mut state = State::new(&window).await;
-
-    let mut last_update_time = Instant::now();
+    let mut state = State::new(&window, &startup_config).await?;
 
     event_loop
         .run(move |event, target| match event {
@@ -554,159 +965,440 @@ pub async fn run() {
                 ref event,
                 window_id,
             } if window_id == state.window.id() => {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    state: ElementState::Pressed,
-                                    physical_key:
-                                        winit::keyboard::PhysicalKey::Code(
-                                            winit::keyboard::KeyCode::Escape,
-                                        ),
-                                    ..
-                                },
-                            ..
-                        } => target.exit(),
-                        // Capuring input this way works for both native and web.
-                        // However, for web, the canvas must be focused for the input to be captured.
-                        // R - randomise grid
-                        WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    state: ElementState::Pressed,
-                                    physical_key:
-                                        winit::keyboard::PhysicalKey::Code(
-                                            winit::keyboard::KeyCode::KeyR,
-                                        ),
-                                    ..
-                                },
-                            ..
-                        } => {
-                            state.reset_cell_state(InitialCellState::Random);
-                            if state.paused {
-                                state.window.request_redraw();
-                                last_update_time = Instant::now();
-                            }
-                        }
-                        // C - clear grid
-                        WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    state: ElementState::Pressed,
-                                    physical_key:
-                                        winit::keyboard::PhysicalKey::Code(
-                                            winit::keyboard::KeyCode::KeyC,
-                                        ),
-                                    ..
-                                },
-                            ..
-                        } => {
-                            state.reset_cell_state(InitialCellState::Empty);
-                            if state.paused {
-                                state.window.request_redraw();
-                                last_update_time = Instant::now();
-                            }
-                        }
-                        // P - pause/play
-                        WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    state: ElementState::Pressed,
-                                    physical_key:
-                                        winit::keyboard::PhysicalKey::Code(
-                                            winit::keyboard::KeyCode::KeyP,
-                                        ),
-                                    ..
-                                },
-                            ..
-                        } => {
-                            state.paused = !state.paused;
-                        }
-                        // N - next frame
-                        WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    state: ElementState::Pressed,
-                                    physical_key:
-                                        winit::keyboard::PhysicalKey::Code(
-                                            winit::keyboard::KeyCode::KeyN,
-                                        ),
-                                    ..
-                                },
-                            ..
-                        } => {
-                            state.paused = true;
-                            state.window.request_redraw();
-                            last_update_time = Instant::now();
-                        }
-                        // F - faster speed
-                        WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    state: ElementState::Pressed,
-                                    physical_key:
-                                        winit::keyboard::PhysicalKey::Code(
-                                            winit::keyboard::KeyCode::KeyF,
-                                        ),
-                                    ..
-                                },
-                            ..
-                        } => {
-                            state.update_interval = (state.update_interval as f64 * 0.8) as u128;
-                        }
-                        // S - slower speed
-                        WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    state: ElementState::Pressed,
-                                    physical_key:
-                                        winit::keyboard::PhysicalKey::Code(
-                                            winit::keyboard::KeyCode::KeyS,
-                                        ),
-                                    ..
-                                },
-                            ..
-                        } => {
-                            state.update_interval = (state.update_interval as f64 * 1.2) as u128;
-                        }
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
-                        }
-                        WindowEvent::RedrawRequested => {
-                            state.update();
-                            match state.render() {
-                                Ok(_) => {}
-                                // Reconfigure the surface if lost
-                                Err(wgpu::SurfaceError::Lost) => state.resize(state.window_size),
-                                // The system is out of memory, we should probably quit
-                                Err(wgpu::SurfaceError::OutOfMemory) => target.exit(),
-                                // All other errors (Outdated, Timeout) should be resolved by the next frame
-                                Err(e) => eprintln!("{:?}", e),
-                            }
+                if state.input(event) {
+                    return;
+                }
+
+                match event {
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::Escape,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => target.exit(),
+                    // Capuring input this way works for both native and web.
+                    // However, for web, the canvas must be focused for the input to be captured.
+                    // R - randomise grid
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyR,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.simulation.randomise();
+                        state.pending_steps = 0;
+                        state.window.request_redraw();
+                    }
+                    // C - clear grid
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyC,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.simulation.clear();
+                        state.pending_steps = 0;
+                        state.window.request_redraw();
+                    }
+                    // P - pause/play
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyP,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.paused = !state.paused;
+                        state.scheduler.reset();
+                    }
+                    // N - next frame
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyN,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.paused = true;
+                        state.pending_steps += 1;
+                        state.window.request_redraw();
+                    }
+                    // F - faster speed
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyF,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.change_speed(1.25);
+                    }
+                    // S - slower speed
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyS,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.change_speed(1.0 / 1.2);
+                    }
+                    // Arrow keys - pan the view
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        key @ (winit::keyboard::KeyCode::ArrowUp
+                                        | winit::keyboard::KeyCode::ArrowDown
+                                        | winit::keyboard::KeyCode::ArrowLeft
+                                        | winit::keyboard::KeyCode::ArrowRight),
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        // The arrow key moves the view, so the grid moves the opposite way
+                        let clip_delta = match key {
+                            winit::keyboard::KeyCode::ArrowUp => [0.0, -PAN_STEP],
+                            winit::keyboard::KeyCode::ArrowDown => [0.0, PAN_STEP],
+                            winit::keyboard::KeyCode::ArrowLeft => [PAN_STEP, 0.0],
+                            _ => [-PAN_STEP, 0.0],
+                        };
+                        state.pan_view(clip_delta);
+                    }
+                    // 1 to 9 - choose the state to paint with
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        key @ (winit::keyboard::KeyCode::Digit1
+                                        | winit::keyboard::KeyCode::Digit2
+                                        | winit::keyboard::KeyCode::Digit3
+                                        | winit::keyboard::KeyCode::Digit4
+                                        | winit::keyboard::KeyCode::Digit5
+                                        | winit::keyboard::KeyCode::Digit6
+                                        | winit::keyboard::KeyCode::Digit7
+                                        | winit::keyboard::KeyCode::Digit8
+                                        | winit::keyboard::KeyCode::Digit9),
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let brush = match key {
+                            winit::keyboard::KeyCode::Digit1 => 1,
+                            winit::keyboard::KeyCode::Digit2 => 2,
+                            winit::keyboard::KeyCode::Digit3 => 3,
+                            winit::keyboard::KeyCode::Digit4 => 4,
+                            winit::keyboard::KeyCode::Digit5 => 5,
+                            winit::keyboard::KeyCode::Digit6 => 6,
+                            winit::keyboard::KeyCode::Digit7 => 7,
+                            winit::keyboard::KeyCode::Digit8 => 8,
+                            _ => 9,
+                        };
+                        state.set_brush(brush);
+                    }
+                    // 0 - fit the grid to the window, and reset the orbit camera
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::Digit0,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.fit_view();
+                    }
+                    // L - switch between letterboxing and filling the window
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyL,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.toggle_aspect_mode();
+                    }
+                    // A - switch between colouring cells by position and by age
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyA,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.toggle_colour_mode();
+                    }
+                    // T - show or hide trails of recently dead cells
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyT,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.toggle_trails();
+                    }
+                    // K - cycle through the colour palettes
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyK,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.next_palette();
+                    }
+                    // O - cycle through the grid topologies
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyO,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.next_topology();
+                    }
+                    // B - show or hide grid lines between the cells
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyB,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.toggle_grid_lines();
+                    }
+                    // G - show or hide the population graph
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyG,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.toggle_graph();
+                    }
+                    // E - export the current generation as RLE
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::KeyE,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        state.export_rle();
+                    }
+                    // ] - double grid size
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::BracketRight,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let simulation = &state.simulation;
+                        let (width, height) = (simulation.width(), simulation.height());
+                        let depth = simulation.depth();
+                        state.resize_grid(width * 2, height * 2, depth * 2);
+                    }
+                    // [ - halve grid size
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key:
+                                    winit::keyboard::PhysicalKey::Code(
+                                        winit::keyboard::KeyCode::BracketLeft,
+                                    ),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let simulation = &state.simulation;
+                        let (width, height) = (simulation.width(), simulation.height());
+                        let depth = simulation.depth();
+                        state.resize_grid(width / 2, height / 2, depth / 2);
+                    }
+                    WindowEvent::Resized(physical_size) => {
+                        state.resize(*physical_size);
+                    }
+                    WindowEvent::RedrawRequested => {
+                        state.update();
+                        match state.render() {
+                            Ok(_) => {}
+                            // Reconfigure the surface if lost
+                            Err(wgpu::SurfaceError::Lost) => state.resize(state.window_size),
+                            // The system is out of memory, we should probably quit
+                            Err(wgpu::SurfaceError::OutOfMemory) => target.exit(),
+                            // All other errors (Outdated, Timeout) should be resolved by the next frame
+                            Err(e) => eprintln!("{:?}", e),
                         }
-                        _ => {}
                     }
+                    _ => {}
                 }
             }
-            Event::AboutToWait => {
-                if state.paused == false {
-                    let now = Instant::now();
-                    if now.duration_since(last_update_time).as_millis() >= state.update_interval {
-                        // Draw the next frame of the simulation
-                        state.window.request_redraw();
-
-                        last_update_time = now;
-                    }
-
-                    // When the event loop finishes, immediately begin a new iteration.
-                    // This is needed to prevent the event loop from idling.
-                    // Note: This only occurs while the simulation is not paused.
-                    target.set_control_flow(winit::event_loop::ControlFlow::Poll);
+            Event::AboutToWait if !state.paused => {
+                // Queue up the generations that are due. Several generations may be simulated
+                // per frame at high speeds, while at low speeds most frames aren't redrawn.
+                let generations = state.scheduler.tick();
+                if generations > 0 {
+                    // Draw the next frame of the simulation
+                    state.pending_steps = pending_generations(state.pending_steps, generations);
+                    state.window.request_redraw();
                 }
+
+                // When the event loop finishes, immediately begin a new iteration.
+                // This is needed to prevent the event loop from idling.
+                // Note: This only occurs while the simulation is not paused.
+                target.set_control_flow(winit::event_loop::ControlFlow::Poll);
+            }
+            Event::AboutToWait => {
+                // Nothing changes while paused, so wait for the next event
+                target.set_control_flow(winit::event_loop::ControlFlow::Wait);
             }
             _ => {}
         })
         .expect("Failed to run event loop");
+    Ok(())
+}
+
+// Step the GPU simulation and the CPU reference simulation in lockstep, reporting the first
+// generation and cell where they diverge
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn run_verification(
+    startup_config: &Config,
+    generations: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt::init();
+
+    let width = startup_config.grid_width;
+    let height = startup_config.grid_height;
+    let depth = startup_config.grid_depth;
+    let rule = startup_config.rule;
+
+    let mut gpu_simulation = Simulation::new(width, height, rule).await?;
+    let mut cpu_simulation = CpuSimulation::new(width, height, rule);
+    if rule.is_three_dimensional() {
+        gpu_simulation.resize_3d(width, height, depth);
+        cpu_simulation.resize_3d(width, height, depth);
+    }
+    gpu_simulation.set_topology(startup_config.topology);
+    cpu_simulation.set_topology(startup_config.topology);
+
+    // Both engines start from the same cells
+    match &startup_config.pattern {
+        Some(pattern) => cpu_simulation.load_pattern(pattern),
+        None => cpu_simulation.randomise(),
+    }
+    gpu_simulation.write_cells(&cpu_simulation.read_cells());
+    if rule.is_continuous() {
+        gpu_simulation.write_values(&cpu_simulation.read_values());
+    }
+
+    let size = if rule.is_three_dimensional() {
+        format!("{width}x{height}x{depth}")
+    } else {
+        format!("{width}x{height}")
+    };
+    info!(
+        "Verifying {} generations of {} on a {} {} grid",
+        generations, rule, size, startup_config.topology
+    );
+    verify(&mut cpu_simulation, &mut gpu_simulation, generations)?;
+    info!("The GPU and CPU simulations matched for {} generations", generations);
+
+    Ok(())
 }
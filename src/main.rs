@@ -1,5 +1,25 @@
-use life_wgpu::run;
+use life_wgpu::{run_with_config, Config};
 
 fn main() {
-    pollster::block_on(run());
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(1);
+        }
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(generations) = config.verify_generations {
+        if let Err(e) = pollster::block_on(life_wgpu::run_verification(&config, generations)) {
+            eprintln!("Verification failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Err(e) = pollster::block_on(run_with_config(config)) {
+        eprintln!("Failed to start: {e}");
+        std::process::exit(1);
+    }
 }
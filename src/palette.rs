@@ -0,0 +1,308 @@
+// Colour palettes for the grid: the built-in themes, and user-defined palettes loaded from a
+// palette file. A palette file holds one or more palettes, each starting with its name:
+//
+//     # Lines starting with # are comments
+//     palette = Sunset
+//     background = #201010
+//     alive = #ffaa33
+//     lamps = #ff0000 #00ff00 #0000ff
+//     age = #ffffff #ffd933 #e63333 #334ce6
+//     wireworld = #3399ff #ff4d33 #ffb31a
+//
+// `background` is the clear colour, and may have an alpha channel (#rrggbbaa).
+// Live cells are coloured `alive`, plus a light from each of the three lamps placed around
+// the grid (bottom left, top middle and bottom right), so palettes can colour cells by
+// position. `age` is the gradient used when colouring cells by age, from newborn to old.
+// `wireworld` colours Wireworld's electron heads, electron tails and conductors.
+// Any field that isn't given keeps its default: white cells on an opaque black background,
+// no lamps, and the default age gradient and Wireworld colours.
+
+use std::fmt;
+
+type Rgb = [f32; 3];
+type Rgba = [f32; 4];
+
+const NO_LAMPS: [Rgb; 3] = [[0.0; 3]; 3];
+const DEFAULT_AGE_GRADIENT: [Rgb; 4] = [
+    [1.0, 1.0, 1.0],
+    [1.0, 0.85, 0.2],
+    [0.9, 0.2, 0.2],
+    [0.2, 0.3, 0.9],
+];
+// Blue electron heads, red tails and copper conductors
+const DEFAULT_WIREWORLD: [Rgb; 3] = [[0.2, 0.6, 1.0], [1.0, 0.3, 0.2], [1.0, 0.7, 0.1]];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub background: Rgba,
+    pub alive: Rgb,
+    pub lamps: [Rgb; 3],
+    pub age_gradient: [Rgb; 4],
+    // Wireworld's electron head, electron tail and conductor
+    pub wireworld: [Rgb; 3],
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PaletteError {
+    // A field was given before the first `palette = ..` line
+    MissingName(usize),
+    // The line isn't a `key = value` pair
+    InvalidLine(usize, String),
+    UnknownField(usize, String),
+    InvalidColour(usize, String),
+    // The field has the wrong number of colours
+    WrongColourCount(usize, String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::MissingName(line) => {
+                write!(
+                    f,
+                    "line {line}: expected 'palette = <name>' before any colours"
+                )
+            }
+            PaletteError::InvalidLine(line, text) => {
+                write!(f, "line {line}: expected 'key = value', found '{text}'")
+            }
+            PaletteError::UnknownField(line, field) => {
+                write!(f, "line {line}: unknown field '{field}'")
+            }
+            PaletteError::InvalidColour(line, colour) => {
+                write!(
+                    f,
+                    "line {line}: invalid colour '{colour}', expected #rrggbb"
+                )
+            }
+            PaletteError::WrongColourCount(line, field) => {
+                write!(f, "line {line}: wrong number of colours for '{field}'")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl Palette {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            background: [0.0, 0.0, 0.0, 1.0],
+            alive: [1.0, 1.0, 1.0],
+            lamps: NO_LAMPS,
+            age_gradient: DEFAULT_AGE_GRADIENT,
+            wireworld: DEFAULT_WIREWORLD,
+        }
+    }
+
+    pub fn built_in() -> Vec<Self> {
+        vec![
+            // The original semi-transparent background, with red, green and blue lamps
+            Self {
+                background: [0.0, 0.0, 0.0, 0.5],
+                alive: [0.0, 0.0, 0.0],
+                lamps: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+                ..Self::new("Lamp")
+            },
+            Self {
+                age_gradient: [
+                    [1.0, 1.0, 1.0],
+                    [0.75, 0.75, 0.75],
+                    [0.5, 0.5, 0.5],
+                    [0.3, 0.3, 0.3],
+                ],
+                ..Self::new("Classic")
+            },
+            Self {
+                background: [1.0, 1.0, 1.0, 1.0],
+                alive: [0.0, 0.0, 0.0],
+                age_gradient: [
+                    [0.0, 0.0, 0.0],
+                    [0.0, 0.0, 0.75],
+                    [0.75, 0.0, 0.0],
+                    [0.0, 0.45, 0.0],
+                ],
+                wireworld: [[0.0, 0.0, 0.8], [0.8, 0.0, 0.0], [0.35, 0.35, 0.35]],
+                ..Self::new("High contrast")
+            },
+            // Okabe-Ito sky blue and Wireworld colours, and the viridis colour map for ages
+            Self {
+                alive: [0.34, 0.71, 0.91],
+                age_gradient: [
+                    [0.99, 0.91, 0.14],
+                    [0.21, 0.72, 0.47],
+                    [0.19, 0.41, 0.56],
+                    [0.27, 0.0, 0.33],
+                ],
+                wireworld: [[0.34, 0.71, 0.91], [0.84, 0.37, 0.0], [0.94, 0.89, 0.26]],
+                ..Self::new("Colour-blind safe")
+            },
+        ]
+    }
+
+    // Parse the palettes in a palette file
+    pub fn parse_file(text: &str) -> Result<Vec<Self>, PaletteError> {
+        let mut palettes: Vec<Self> = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim().to_ascii_lowercase(), value.trim()))
+                .ok_or_else(|| PaletteError::InvalidLine(line_number, line.to_string()))?;
+
+            if key == "palette" {
+                palettes.push(Self::new(value));
+                continue;
+            }
+            let palette = palettes
+                .last_mut()
+                .ok_or(PaletteError::MissingName(line_number))?;
+
+            let colours = value
+                .split_whitespace()
+                .map(|colour| {
+                    parse_colour(colour)
+                        .ok_or_else(|| PaletteError::InvalidColour(line_number, colour.to_string()))
+                })
+                .collect::<Result<Vec<Rgba>, _>>()?;
+            let wrong_count = || PaletteError::WrongColourCount(line_number, key.clone());
+
+            match key.as_str() {
+                "background" => match colours[..] {
+                    [colour] => palette.background = colour,
+                    _ => return Err(wrong_count()),
+                },
+                "alive" => match colours[..] {
+                    [colour] => palette.alive = rgb(colour),
+                    _ => return Err(wrong_count()),
+                },
+                "lamps" => match colours[..] {
+                    [a, b, c] => palette.lamps = [rgb(a), rgb(b), rgb(c)],
+                    _ => return Err(wrong_count()),
+                },
+                "age" => match colours[..] {
+                    [a, b, c, d] => palette.age_gradient = [rgb(a), rgb(b), rgb(c), rgb(d)],
+                    _ => return Err(wrong_count()),
+                },
+                "wireworld" => match colours[..] {
+                    [a, b, c] => palette.wireworld = [rgb(a), rgb(b), rgb(c)],
+                    _ => return Err(wrong_count()),
+                },
+                _ => return Err(PaletteError::UnknownField(line_number, key)),
+            }
+        }
+
+        Ok(palettes)
+    }
+}
+
+fn rgb(colour: Rgba) -> Rgb {
+    [colour[0], colour[1], colour[2]]
+}
+
+// Parse a #rrggbb or #rrggbbaa hex colour. The # is optional.
+fn parse_colour(colour: &str) -> Option<Rgba> {
+    let hex = colour.strip_prefix('#').unwrap_or(colour);
+    // from_str_radix would also take a sign, e.g. +f
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let mut rgba = [1.0; 4];
+    for (channel, i) in rgba.iter_mut().zip((0..hex.len()).step_by(2)) {
+        *channel = u8::from_str_radix(&hex[i..i + 2], 16).ok()? as f32 / 255.0;
+    }
+    Some(rgba)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_files_are_parsed() {
+        let text = "
+            # Two palettes, the second with nothing but a name
+            palette = Sunset
+            background = #20101080
+            alive = ffaa33
+            lamps = #ff0000 #00ff00 #0000FF
+            AGE = #ffffff #ffffff #000000 #000000
+
+            palette = Plain
+        ";
+        let palettes = Palette::parse_file(text).unwrap();
+        assert_eq!(
+            palettes,
+            vec![
+                Palette {
+                    background: [32.0 / 255.0, 16.0 / 255.0, 16.0 / 255.0, 128.0 / 255.0],
+                    alive: [1.0, 170.0 / 255.0, 51.0 / 255.0],
+                    lamps: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+                    age_gradient: [[1.0; 3], [1.0; 3], [0.0; 3], [0.0; 3]],
+                    ..Palette::new("Sunset")
+                },
+                Palette::new("Plain"),
+            ]
+        );
+    }
+
+    #[test]
+    fn fields_need_the_right_number_of_colours() {
+        assert_eq!(
+            Palette::parse_file("palette = a\nlamps = #ff0000 #00ff00"),
+            Err(PaletteError::WrongColourCount(2, "lamps".to_string()))
+        );
+        assert_eq!(
+            Palette::parse_file("palette = a\nalive = #ff0000 #00ff00"),
+            Err(PaletteError::WrongColourCount(2, "alive".to_string()))
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert_eq!(
+            Palette::parse_file("palette = a\ncolour = #ffffff"),
+            Err(PaletteError::UnknownField(2, "colour".to_string()))
+        );
+    }
+
+    #[test]
+    fn colours_need_a_palette_name_first() {
+        assert_eq!(
+            Palette::parse_file("# No name\nalive = #ffffff"),
+            Err(PaletteError::MissingName(2))
+        );
+        assert_eq!(
+            Palette::parse_file("palette = a\nalive #ffffff"),
+            Err(PaletteError::InvalidLine(2, "alive #ffffff".to_string()))
+        );
+    }
+
+    #[test]
+    fn colours_must_be_hex() {
+        for colour in [
+            "#+f+f+f",
+            "#fff",
+            "#gg0000",
+            "#ff00001",
+            "#ff00ff00ff",
+            "#ffé000",
+            "#",
+        ] {
+            assert_eq!(
+                Palette::parse_file(&format!("palette = a\nalive = {colour}")),
+                Err(PaletteError::InvalidColour(2, colour.to_string())),
+                "{colour}"
+            );
+        }
+    }
+}
@@ -0,0 +1,551 @@
+// Counts the population of every generation on the GPU, without reading back the cell state.
+// After each compute pass a parallel reduction (population.wgsl) sums the output buffer into
+// a single atomic counter, which is copied into a small staging buffer. Once the commands have
+// been submitted the staging buffer is mapped asynchronously, and the counts are collected
+// into the population history whenever they're ready.
+// What the population is depends on the rule: see PopulationMeasure. One dimensional rules keep
+// their history in the rest of the grid, so only the current generation's row is counted.
+
+use crate::rule::Rule;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::mpsc;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, warn};
+use wgpu::util::DeviceExt;
+
+// Must match WORKGROUP_SIZE in population.wgsl
+const POPULATION_WORKGROUP_SIZE: u32 = 256;
+// Large grids are covered by each invocation summing several cells, rather than by
+// dispatching more workgroups that would all contend for the same atomic counter
+const MAX_WORKGROUPS: u32 = 256;
+
+// How many generations of population are kept
+pub const POPULATION_HISTORY_LENGTH: usize = 1024;
+// Log the population every this many generations
+const LOG_INTERVAL: u32 = 100;
+
+const COUNT_SIZE: u64 = std::mem::size_of::<u32>() as u64;
+
+// Lenia's mass is summed in units of 1 / MASS_SCALE of a cell. This keeps the sum of the largest
+// grid full of cells with the largest value within the counter. Must match MASS_SCALE in
+// population.wgsl.
+const MASS_SCALE: u32 = 128;
+
+// What counts towards the population of a generation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PopulationMeasure {
+    // Cells that aren't dead, including the dying cells of Generations rules, as Golly
+    // counts them
+    Occupied,
+    // Wireworld's electrons, counted by their heads, as its conductors never change
+    Electrons,
+    // The sum of Lenia's cell values, rounded to a whole number of cells
+    Mass,
+}
+
+impl From<Rule> for PopulationMeasure {
+    fn from(rule: Rule) -> Self {
+        match rule {
+            Rule::Wireworld => PopulationMeasure::Electrons,
+            Rule::Lenia(_) => PopulationMeasure::Mass,
+            Rule::Life(_) | Rule::LargerThanLife(_) | Rule::Elementary(_) | Rule::Life3D(_) => {
+                PopulationMeasure::Occupied
+            }
+        }
+    }
+}
+
+impl PopulationMeasure {
+    // The population of a generation from the sum of its cells
+    fn population(self, sum: u32) -> u32 {
+        match self {
+            PopulationMeasure::Occupied | PopulationMeasure::Electrons => sum,
+            PopulationMeasure::Mass => (sum + MASS_SCALE / 2) / MASS_SCALE,
+        }
+    }
+}
+
+// Must match Measure in population.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PopulationUniform {
+    measure: u32,
+    // The number of cells in the row of the current generation, for one dimensional rules, or
+    // 0 to count the whole grid
+    row_width: u32,
+    _padding: [u32; 2],
+}
+
+impl PopulationUniform {
+    fn new(rule: Rule, width: u32) -> Self {
+        Self {
+            measure: PopulationMeasure::from(rule) as u32,
+            row_width: if rule.is_one_dimensional() { width } else { 0 },
+            _padding: [0; 2],
+        }
+    }
+}
+
+// The population of a generation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PopulationSample {
+    pub generation: u32,
+    pub population: u32,
+}
+
+// A staging buffer holding the counts of a run of consecutive generations
+struct Readback {
+    buffer: wgpu::Buffer,
+    first_generation: u32,
+    count: u32,
+    epoch: u32, // the epoch when the counts were recorded
+    measure: PopulationMeasure,
+    mapped: Option<mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+pub(crate) struct PopulationCounter {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    counter_buffer: wgpu::Buffer,
+    rule: Rule,
+    measure: PopulationMeasure,
+    uniform_buffer: wgpu::Buffer,
+    // Count the cells in buffer a (even generations) or buffer b (odd generations)
+    bind_group_a: wgpu::BindGroup,
+    bind_group_b: wgpu::BindGroup,
+    width: u32,
+    num_cells: u32,
+    // Incremented whenever the grid is reset, so that counts of the old grid still in
+    // flight can be told apart and dropped
+    epoch: u32,
+    recording: Option<Readback>, // the counts being recorded into the current encoder
+    unmapped: Vec<Readback>,     // submitted but not yet mapped
+    in_flight: VecDeque<Readback>,
+    free_buffers: Vec<wgpu::Buffer>,
+    history: VecDeque<PopulationSample>,
+}
+
+impl PopulationCounter {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        [state_a, state_b]: [&wgpu::Buffer; 2],
+        [value_a, value_b]: [&wgpu::Buffer; 2],
+        history_uniform: &wgpu::Buffer,
+        width: u32,
+        num_cells: u32,
+        rule: Rule,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    // cell states to count (read only)
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // population counter (read-write)
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // cell values to sum for continuous rules (read only)
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // what counts towards the population
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // the row of the current generation, for one dimensional rules
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("population_bind_group_layout"),
+        });
+
+        let counter_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Population Counter Buffer"),
+            size: COUNT_SIZE,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let measure = PopulationMeasure::from(rule);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Population Uniforms"),
+            contents: bytemuck::cast_slice(&[PopulationUniform::new(rule, width)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Population Shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("population.wgsl"))),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Population Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Population Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_count",
+        });
+
+        let create_bind_group = |cell_state_buffer, value_buffer| {
+            Self::create_bind_group(
+                device,
+                &bind_group_layout,
+                [cell_state_buffer, value_buffer],
+                &counter_buffer,
+                [&uniform_buffer, history_uniform],
+            )
+        };
+        let bind_group_a = create_bind_group(state_a, value_a);
+        let bind_group_b = create_bind_group(state_b, value_b);
+
+        Self {
+            bind_group_layout,
+            pipeline,
+            counter_buffer,
+            rule,
+            measure,
+            uniform_buffer,
+            bind_group_a,
+            bind_group_b,
+            width,
+            num_cells,
+            epoch: 0,
+            recording: None,
+            unmapped: Vec::new(),
+            in_flight: VecDeque::new(),
+            free_buffers: Vec::new(),
+            history: VecDeque::with_capacity(POPULATION_HISTORY_LENGTH),
+        }
+    }
+
+    // Bind the state and value buffers of one generation's cells
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        [cell_state_buffer, value_buffer]: [&wgpu::Buffer; 2],
+        counter_buffer: &wgpu::Buffer,
+        [uniform_buffer, history_uniform]: [&wgpu::Buffer; 2],
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: cell_state_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: counter_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: value_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: history_uniform.as_entire_binding(),
+                },
+            ],
+            label: Some("Population Bind Group"),
+        })
+    }
+
+    // Count a new pair of cell state buffers after the grid has been resized
+    pub(crate) fn set_cell_state_buffers(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        [state_a, state_b]: [&wgpu::Buffer; 2],
+        [value_a, value_b]: [&wgpu::Buffer; 2],
+        history_uniform: &wgpu::Buffer,
+        [width, num_cells]: [u32; 2],
+    ) {
+        let create_bind_group = |cell_state_buffer, value_buffer| {
+            Self::create_bind_group(
+                device,
+                &self.bind_group_layout,
+                [cell_state_buffer, value_buffer],
+                &self.counter_buffer,
+                [&self.uniform_buffer, history_uniform],
+            )
+        };
+        self.bind_group_a = create_bind_group(state_a, value_a);
+        self.bind_group_b = create_bind_group(state_b, value_b);
+        self.width = width;
+        self.num_cells = num_cells;
+        self.write_uniform(queue);
+        self.reset();
+    }
+
+    // Count the population as the rule defines it from now on
+    pub(crate) fn set_rule(&mut self, queue: &wgpu::Queue, rule: Rule) {
+        self.rule = rule;
+        self.measure = PopulationMeasure::from(rule);
+        self.write_uniform(queue);
+    }
+
+    fn write_uniform(&self, queue: &wgpu::Queue) {
+        let uniform = PopulationUniform::new(self.rule, self.width);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Forget the history, e.g. when the grid is cleared or randomised
+    pub(crate) fn reset(&mut self) {
+        self.epoch = self.epoch.wrapping_add(1);
+        self.history.clear();
+    }
+
+    // Start recording the counts of up to n consecutive generations into an encoder
+    pub(crate) fn begin(&mut self, device: &wgpu::Device, first_generation: u32, n: u32) {
+        let size = n as u64 * COUNT_SIZE;
+        let buffer = match self
+            .free_buffers
+            .iter()
+            .position(|buffer| buffer.size() >= size)
+        {
+            Some(i) => self.free_buffers.swap_remove(i),
+            None => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Population Readback Buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        };
+
+        self.recording = Some(Readback {
+            buffer,
+            first_generation,
+            count: 0,
+            epoch: self.epoch,
+            measure: self.measure,
+            mapped: None,
+        });
+    }
+
+    // Record counting the live cells of a generation. The generation's cells are in buffer a
+    // if it's even, and buffer b if it's odd.
+    pub(crate) fn encode_count(&mut self, encoder: &mut wgpu::CommandEncoder, generation: u32) {
+        let readback = self
+            .recording
+            .as_mut()
+            .expect("begin must be called before encode_count");
+        debug_assert_eq!(readback.first_generation + readback.count, generation);
+
+        encoder.clear_buffer(&self.counter_buffer, 0, None);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Population Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        let bind_group = if generation.is_multiple_of(2) {
+            &self.bind_group_a
+        } else {
+            &self.bind_group_b
+        };
+        compute_pass.set_bind_group(0, bind_group, &[]);
+
+        let counted_cells = if self.rule.is_one_dimensional() {
+            self.width
+        } else {
+            self.num_cells
+        };
+        let workgroup_count = counted_cells
+            .div_ceil(POPULATION_WORKGROUP_SIZE)
+            .clamp(1, MAX_WORKGROUPS);
+        compute_pass.dispatch_workgroups(workgroup_count, 1, 1);
+        drop(compute_pass);
+
+        encoder.copy_buffer_to_buffer(
+            &self.counter_buffer,
+            0,
+            &readback.buffer,
+            readback.count as u64 * COUNT_SIZE,
+            COUNT_SIZE,
+        );
+        readback.count += 1;
+    }
+
+    // Finish recording into the current encoder. Call submitted once it has been submitted.
+    pub(crate) fn end(&mut self) {
+        if let Some(readback) = self.recording.take() {
+            if readback.count > 0 {
+                self.unmapped.push(readback);
+            } else {
+                self.free_buffers.push(readback.buffer);
+            }
+        }
+    }
+
+    // Start mapping the counts recorded into submitted encoders
+    pub(crate) fn submitted(&mut self) {
+        for mut readback in self.unmapped.drain(..) {
+            let (sender, receiver) = mpsc::channel();
+            readback
+                .buffer
+                .slice(..readback.count as u64 * COUNT_SIZE)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    // The receiver is gone if the counter was dropped before the map finished
+                    let _ = sender.send(result);
+                });
+            readback.mapped = Some(receiver);
+            self.in_flight.push_back(readback);
+        }
+    }
+
+    // Collect any counts that have been mapped, without blocking
+    pub(crate) fn poll(&mut self) {
+        // Buffers are mapped in submission order, so stop at the first that isn't ready
+        while let Some(readback) = self.in_flight.front() {
+            let result = match &readback.mapped {
+                Some(receiver) => match receiver.try_recv() {
+                    Ok(result) => result,
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => Err(wgpu::BufferAsyncError),
+                },
+                None => break,
+            };
+            let readback = self.in_flight.pop_front().unwrap();
+
+            match result {
+                Ok(()) => {
+                    if readback.epoch == self.epoch {
+                        let size = readback.count as u64 * COUNT_SIZE;
+                        let counts: Vec<u32> =
+                            bytemuck::cast_slice(&readback.buffer.slice(..size).get_mapped_range())
+                                .to_vec();
+                        for (generation, sum) in (readback.first_generation..).zip(counts) {
+                            self.record(PopulationSample {
+                                generation,
+                                population: readback.measure.population(sum),
+                            });
+                        }
+                    }
+                    readback.buffer.unmap();
+                    self.free_buffers.push(readback.buffer);
+                }
+                Err(e) => warn!("Failed to map population readback buffer: {}", e),
+            }
+        }
+    }
+
+    fn record(&mut self, sample: PopulationSample) {
+        debug!(
+            "Generation {}: population {}",
+            sample.generation, sample.population
+        );
+        if sample.generation.is_multiple_of(LOG_INTERVAL) {
+            info!(
+                "Generation {}: population {}",
+                sample.generation, sample.population
+            );
+        }
+
+        // A generation is counted again if its cells were overwritten
+        if self.history.back().map(|last| last.generation) == Some(sample.generation) {
+            self.history.pop_back();
+        }
+        if self.history.len() == POPULATION_HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+    }
+
+    pub(crate) fn latest(&self) -> Option<PopulationSample> {
+        self.history.back().copied()
+    }
+
+    pub(crate) fn history(&self) -> &VecDeque<PopulationSample> {
+        &self.history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_choose_their_measure() {
+        let measure = |rule: &str| PopulationMeasure::from(rule.parse::<Rule>().unwrap());
+        assert_eq!(measure("B3/S23"), PopulationMeasure::Occupied);
+        assert_eq!(measure("B2/S/C3"), PopulationMeasure::Occupied);
+        assert_eq!(
+            measure("R1,C0,M0,S2..3,B3..3,NM"),
+            PopulationMeasure::Occupied
+        );
+        assert_eq!(measure("W30"), PopulationMeasure::Occupied);
+        assert_eq!(measure("4555"), PopulationMeasure::Occupied);
+        assert_eq!(measure("WireWorld"), PopulationMeasure::Electrons);
+        assert_eq!(measure("Lenia"), PopulationMeasure::Mass);
+    }
+
+    #[test]
+    fn only_the_current_row_of_one_dimensional_rules_is_counted() {
+        let row_width = |rule: &str| PopulationUniform::new(rule.parse().unwrap(), 64).row_width;
+        assert_eq!(row_width("W30"), 64);
+        assert_eq!(row_width("B3/S23"), 0);
+        assert_eq!(row_width("4555"), 0);
+    }
+
+    #[test]
+    fn mass_is_rounded_to_whole_cells() {
+        let mass = |sum| PopulationMeasure::Mass.population(sum);
+        assert_eq!(mass(0), 0);
+        assert_eq!(mass(MASS_SCALE / 2 - 1), 0);
+        assert_eq!(mass(MASS_SCALE / 2), 1);
+        assert_eq!(mass(10 * MASS_SCALE + 3), 10);
+        // The largest grid full of cells with the largest value
+        let max_sum = crate::config::MAX_GRID_CELLS * MASS_SCALE;
+        assert_eq!(mass(max_sum), crate::config::MAX_GRID_CELLS);
+        assert_eq!(PopulationMeasure::Occupied.population(42), 42);
+    }
+}
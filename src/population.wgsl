@@ -0,0 +1,91 @@
+@group(0) @binding(0) var<storage> cells: array<u32>;
+@group(0) @binding(1) var<storage, read_write> population: atomic<u32>;
+@group(0) @binding(2) var<storage> values: array<f32>;
+@group(0) @binding(3) var<uniform> measure: Measure;
+@group(0) @binding(4) var<uniform> history: History;
+
+// What counts towards the population. Must match PopulationUniform in population.rs.
+struct Measure {
+    kind: u32,
+    // The number of cells in the current generation's row, for one dimensional rules, or 0 to
+    // count the whole grid
+    row_width: u32,
+};
+
+// The row holding the current generation of a one dimensional rule. Must match HistoryUniform
+// in simulation.rs.
+struct History {
+    row: u32,
+};
+
+// Must match PopulationMeasure in population.rs
+const MEASURE_OCCUPIED: u32 = 0u;
+const MEASURE_ELECTRONS: u32 = 1u;
+const MEASURE_MASS: u32 = 2u;
+// Must match MASS_SCALE in population.rs
+const MASS_SCALE: f32 = 128.0;
+
+// Must match WIREWORLD_HEAD in rule.rs
+const WIREWORLD_HEAD: u32 = 1u;
+
+// Population counter
+
+// Must match POPULATION_WORKGROUP_SIZE in population.rs
+const WORKGROUP_SIZE: u32 = 256u;
+
+var<workgroup> partial_sums: array<u32, WORKGROUP_SIZE>;
+
+// How much a cell adds to the population. Mass is summed in fixed point, as the population
+// counter is an integer.
+fn cell_population(i: u32) -> u32 {
+    switch (measure.kind) {
+        case MEASURE_ELECTRONS: {
+            return select(0u, 1u, cells[i] == WIREWORLD_HEAD);
+        }
+        case MEASURE_MASS: {
+            return u32(round(values[i] * MASS_SCALE));
+        }
+        default: {
+            return select(0u, 1u, cells[i] != 0u);
+        }
+    }
+}
+
+// A parallel reduction: each invocation sums a strided range of cells, the workgroup
+// reduces its partial sums in shared memory, and one invocation per workgroup adds the
+// workgroup's total to the population counter.
+@compute @workgroup_size(256)
+fn cs_count(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    // One dimensional rules keep their history in the rest of the grid, so only the row of the
+    // current generation is counted
+    var first_cell = 0u;
+    var num_cells = arrayLength(&cells);
+    if (measure.row_width != 0u) {
+        first_cell = history.row * measure.row_width;
+        num_cells = measure.row_width;
+    }
+    let stride = num_workgroups.x * WORKGROUP_SIZE;
+
+    var sum = 0u;
+    for (var i = global_id.x; i < num_cells; i += stride) {
+        sum += cell_population(first_cell + i);
+    }
+    partial_sums[local_index] = sum;
+    workgroupBarrier();
+
+    // Halve the number of active invocations each iteration
+    for (var offset = WORKGROUP_SIZE / 2u; offset > 0u; offset /= 2u) {
+        if (local_index < offset) {
+            partial_sums[local_index] += partial_sums[local_index + offset];
+        }
+        workgroupBarrier();
+    }
+
+    if (local_index == 0u) {
+        atomicAdd(&population, partial_sums[0]);
+    }
+}
@@ -0,0 +1,490 @@
+// Run Length Encoded (.rle) patterns, as used by Golly and the LifeWiki.
+// See https://conwaylife.com/wiki/Run_Length_Encoded for the format.
+// Patterns are stored as they're written, with the first row at the top, while the grid's first
+// row is drawn at the bottom, so rows are flipped when moving between the two.
+// Patterns on the hexagonal lattice are stored in this grid's rows, where odd rows are shifted
+// right by half a cell. Golly skews the rows of its hexagonal patterns instead, so these patterns
+// aren't compatible with Golly's.
+
+use crate::config::MAX_GRID_SIZE;
+use crate::lattice::Lattice;
+use crate::rule::{Rule, RuleParseError};
+use std::fmt;
+#[allow(unused_imports)]
+use tracing::{error, info, warn};
+
+// Golly limits RLE lines to 70 characters
+const MAX_LINE_LENGTH: usize = 70;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    pub width: u32,
+    pub height: u32,
+    pub rule: Option<Rule>,
+    // Row-major cell states, 0 for dead cells
+    pub cells: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RleError {
+    // No `x = .., y = ..` header line was found
+    MissingHeader,
+    InvalidHeader(String),
+    InvalidRule(RuleParseError),
+    UnexpectedCharacter(char),
+    // The pattern doesn't fit in the largest grid
+    TooLarge,
+}
+
+impl fmt::Display for RleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RleError::MissingHeader => write!(f, "missing 'x = .., y = ..' header line"),
+            RleError::InvalidHeader(line) => write!(f, "invalid header line '{line}'"),
+            RleError::InvalidRule(e) => write!(f, "invalid rule: {e}"),
+            RleError::UnexpectedCharacter(c) => write!(f, "unexpected character '{c}'"),
+            RleError::TooLarge => write!(
+                f,
+                "pattern is larger than the largest grid ({MAX_GRID_SIZE}x{MAX_GRID_SIZE})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RleError {}
+
+impl From<RuleParseError> for RleError {
+    fn from(e: RuleParseError) -> Self {
+        RleError::InvalidRule(e)
+    }
+}
+
+impl Pattern {
+    pub fn from_rle(rle: &str) -> Result<Self, RleError> {
+        let mut lines = rle
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let header = lines.next().ok_or(RleError::MissingHeader)?;
+        let (mut width, mut height, rule) = parse_header(header)?;
+
+        // Decode the runs into (x, y, state) triples
+        let mut live_cells = Vec::new();
+        let (mut x, mut y) = (0, 0);
+        let mut run_count: Option<u32> = None;
+        // Runs can't reach past the largest grid, which also keeps the positions from overflowing
+        let advance = |position: u32, count: u32| {
+            position
+                .checked_add(count)
+                .filter(|&position| position <= MAX_GRID_SIZE)
+                .ok_or(RleError::TooLarge)
+        };
+
+        'decode: for line in lines {
+            for c in line.chars() {
+                if let Some(digit) = c.to_digit(10) {
+                    let count = run_count.unwrap_or(0).checked_mul(10);
+                    let count = count.and_then(|count| count.checked_add(digit));
+                    run_count = Some(count.ok_or(RleError::TooLarge)?);
+                    continue;
+                }
+
+                let count = run_count.take().unwrap_or(1);
+                match c {
+                    'b' | '.' => x = advance(x, count)?,
+                    'o' | 'A'..='X' => {
+                        let state = if c == 'o' {
+                            1
+                        } else {
+                            c as u32 - 'A' as u32 + 1
+                        };
+                        let end = advance(x, count)?;
+                        live_cells.extend((x..end).map(|x| (x, y, state)));
+                        x = end;
+                    }
+                    '$' => {
+                        x = 0;
+                        y = advance(y, count)?;
+                    }
+                    '!' => break 'decode,
+                    c if c.is_whitespace() => {}
+                    c => return Err(RleError::UnexpectedCharacter(c)),
+                }
+            }
+        }
+
+        // Be lenient with patterns which don't fit inside their declared size
+        for &(x, y, _) in &live_cells {
+            width = width.max(x + 1);
+            height = height.max(y + 1);
+        }
+        if width > MAX_GRID_SIZE || height > MAX_GRID_SIZE {
+            return Err(RleError::TooLarge);
+        }
+
+        let mut cells = vec![0; (width * height) as usize];
+        for (x, y, state) in live_cells {
+            cells[(y * width + x) as usize] = state;
+        }
+
+        Ok(Self {
+            width,
+            height,
+            rule,
+            cells,
+        })
+    }
+
+    // Create a pattern from the bounding box of the live cells in a grid, flipped so that the
+    // grid's top row comes first. The box starts where the rule's lattice aligns patterns.
+    pub fn from_cells(
+        cells: &[u32],
+        grid_width: u32,
+        grid_height: u32,
+        rule: Option<Rule>,
+    ) -> Self {
+        let cell = |x: i64, y: i64| {
+            let inside_x = (0..grid_width as i64).contains(&x);
+            if inside_x && (0..grid_height as i64).contains(&y) {
+                cells[(y * grid_width as i64 + x) as usize]
+            } else {
+                0
+            }
+        };
+        let live = |x: u32, y: u32| cell(x as i64, y as i64) != 0;
+
+        let mut min = (grid_width, grid_height);
+        let mut max = (0, 0);
+        for y in 0..grid_height {
+            for x in 0..grid_width {
+                if live(x, y) {
+                    min = (min.0.min(x), min.1.min(y));
+                    max = (max.0.max(x), max.1.max(y));
+                }
+            }
+        }
+
+        if min.0 > max.0 {
+            // There are no live cells
+            return Self {
+                width: 0,
+                height: 0,
+                rule,
+                cells: Vec::new(),
+            };
+        }
+
+        let lattice = rule.map_or(Lattice::Square, |rule| rule.lattice());
+        let min = lattice.align(min.0 as i64, min.1 as i64);
+        let max = (max.0 as i64, max.1 as i64);
+        let width = (max.0 - min.0 + 1) as u32;
+        let height = (max.1 - min.1 + 1) as u32;
+        let mut pattern_cells = Vec::with_capacity((width * height) as usize);
+        for y in (min.1..=max.1).rev() {
+            pattern_cells.extend((min.0..=max.0).map(|x| cell(x, y)));
+        }
+
+        Self {
+            width,
+            height,
+            rule,
+            cells: pattern_cells,
+        }
+    }
+
+    // The cells of a grid with this pattern placed in its centre, flipped so that the pattern's
+    // first row is at the top. Patterns larger than the grid are cropped to fit.
+    pub fn centred_cells(&self, grid_width: u32, grid_height: u32) -> Vec<u32> {
+        let offset = self.centre_offset(grid_width, grid_height);
+        self.placed_cells(grid_width, grid_height, offset)
+    }
+
+    // Where the bottom left corner of this pattern goes to centre it in a grid. Offsets are
+    // signed so that patterns larger than the grid are centred too.
+    fn centre_offset(&self, grid_width: u32, grid_height: u32) -> (i64, i64) {
+        let offset_x = (grid_width as i64 - self.width as i64) / 2;
+        let offset_y = (grid_height as i64 - self.height as i64) / 2;
+        (offset_x, offset_y)
+    }
+
+    // The cells of a grid with the bottom left corner of this pattern at an offset
+    fn placed_cells(&self, grid_width: u32, grid_height: u32, offset: (i64, i64)) -> Vec<u32> {
+        if self.width > grid_width || self.height > grid_height {
+            warn!(
+                "Pattern of size {}x{} is larger than the {}x{} grid, cropping it to fit",
+                self.width, self.height, grid_width, grid_height
+            );
+        }
+
+        let mut cells = vec![0; (grid_width * grid_height) as usize];
+        let (offset_x, offset_y) = offset;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let grid_x = x as i64 + offset_x;
+                let grid_y = (self.height - 1 - y) as i64 + offset_y;
+                if (0..grid_width as i64).contains(&grid_x)
+                    && (0..grid_height as i64).contains(&grid_y)
+                {
+                    cells[(grid_y * grid_width as i64 + grid_x) as usize] =
+                        self.cells[(y * self.width + x) as usize];
+                }
+            }
+        }
+
+        cells
+    }
+
+    // The cells of a grid of one or more layers starting from this pattern under a rule. One
+    // dimensional rules start from the grid's first row, so the pattern's middle row is centred
+    // in it and the rest of the grid is left empty for the history. Patterns are flat, so on a
+    // 3D grid the pattern is centred in the middle layer. Other patterns are centred as far as
+    // the rule's lattice lets them be while keeping their shape.
+    pub(crate) fn starting_cells(
+        &self,
+        rule: Rule,
+        grid_width: u32,
+        grid_height: u32,
+        grid_depth: u32,
+    ) -> Vec<u32> {
+        let layer_cells = (grid_width * grid_height) as usize;
+        let mut cells = vec![0; layer_cells * grid_depth as usize];
+        if rule.is_one_dimensional() {
+            cells[..grid_width as usize].copy_from_slice(&self.centred_cells(grid_width, 1));
+        } else {
+            let (offset_x, offset_y) = self.centre_offset(grid_width, grid_height);
+            let offset = rule.lattice().align(offset_x, offset_y);
+            let layer = (grid_depth / 2) as usize * layer_cells;
+            cells[layer..layer + layer_cells].copy_from_slice(&self.placed_cells(
+                grid_width,
+                grid_height,
+                offset,
+            ));
+        }
+        cells
+    }
+
+    pub fn to_rle(&self) -> String {
+        let mut header = format!("x = {}, y = {}", self.width, self.height);
+        if let Some(rule) = self.rule {
+            header += &format!(", rule = {rule}");
+        }
+
+        // Two state patterns use b/o, multi-state patterns use . for dead cells and A, B, ...
+        // for the live states.
+        let multi_state = self.cells.iter().any(|&state| state > 1);
+
+        // Encode each row as a list of runs, dropping trailing dead cells
+        let mut tokens = Vec::new();
+        let mut pending_row_ends = 0;
+        for row in self.cells.chunks(self.width.max(1) as usize) {
+            let row_length = row
+                .iter()
+                .rposition(|&state| state != 0)
+                .map_or(0, |i| i + 1);
+            if row_length == 0 {
+                pending_row_ends += 1;
+                continue;
+            }
+
+            if pending_row_ends > 0 {
+                tokens.push(run_token(pending_row_ends, '$'));
+                pending_row_ends = 0;
+            }
+
+            let mut x = 0;
+            while x < row_length {
+                let state = row[x];
+                let run_length = row[x..row_length]
+                    .iter()
+                    .take_while(|&&s| s == state)
+                    .count();
+                tokens.push(run_token(run_length, state_char(state, multi_state)));
+                x += run_length;
+            }
+            pending_row_ends += 1;
+        }
+        tokens.push("!".to_string());
+
+        // Wrap the runs onto lines, without splitting a run across lines
+        let mut rle = header + "\n";
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length + token.len() > MAX_LINE_LENGTH {
+                rle.push('\n');
+                line_length = 0;
+            }
+            line_length += token.len();
+            rle += &token;
+        }
+        rle.push('\n');
+        rle
+    }
+}
+
+fn run_token(count: usize, tag: char) -> String {
+    if count == 1 {
+        tag.to_string()
+    } else {
+        format!("{count}{tag}")
+    }
+}
+
+fn state_char(state: u32, multi_state: bool) -> char {
+    match (state, multi_state) {
+        (0, false) => 'b',
+        (_, false) => 'o',
+        (0, true) => '.',
+        (state, true) => char::from_u32('A' as u32 + state.min(24) - 1).unwrap(),
+    }
+}
+
+// Parse a header line of the form `x = m, y = n, rule = abc`
+fn parse_header(line: &str) -> Result<(u32, u32, Option<Rule>), RleError> {
+    let invalid = || RleError::InvalidHeader(line.to_string());
+
+    // The rule comes last and may itself contain commas, so split it off first
+    let (dimensions, rule_entry) = match line.find("rule") {
+        Some(i) => (&line[..i], Some(&line[i..])),
+        None => (line, None),
+    };
+
+    let (mut width, mut height) = (None, None);
+    for entry in dimensions
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+    {
+        let (key, value) = entry.split_once('=').ok_or_else(invalid)?;
+        let value = value.trim().parse::<u32>().map_err(|_| invalid())?;
+        match key.trim() {
+            "x" => width = Some(value),
+            "y" => height = Some(value),
+            _ => return Err(invalid()),
+        }
+    }
+
+    let rule = match rule_entry {
+        Some(entry) => {
+            let (_, value) = entry.split_once('=').ok_or_else(invalid)?;
+            // Golly appends the bounded grid type after a colon, e.g. B3/S23:T64,64.
+            // The grid part is dropped as the board's own size is used instead.
+            let rulestring = value.split(':').next().unwrap_or_default();
+            Some(rulestring.trim().parse::<Rule>()?)
+        }
+        None => None,
+    };
+
+    match (width, height) {
+        (Some(width), Some(height)) => Ok((width, height, rule)),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER: &str = "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+
+    // The glider as it's drawn on an 8x8 grid, whose first row is at the bottom
+    fn glider_cells() -> Vec<u32> {
+        let mut cells = vec![0; 64];
+        for (x, y) in [(2, 2), (3, 2), (4, 2), (4, 3), (3, 4)] {
+            cells[y * 8 + x] = 1;
+        }
+        cells
+    }
+
+    #[test]
+    fn exported_patterns_start_from_the_top_row() {
+        let rule = Some("B3/S23".parse().unwrap());
+        let pattern = Pattern::from_cells(&glider_cells(), 8, 8, rule);
+        assert_eq!(pattern.to_rle(), GLIDER);
+        assert_eq!(Pattern::from_rle(GLIDER).unwrap(), pattern);
+    }
+
+    #[test]
+    fn glider_is_placed_the_right_way_up() {
+        let pattern = Pattern::from_rle(GLIDER).unwrap();
+        let cells = pattern.centred_cells(8, 8);
+        let row = |y: usize| &cells[y * 8..y * 8 + 8];
+
+        // The grid's rows are drawn from the bottom up, so the pattern's last row comes first
+        assert_eq!(row(2), &[0, 0, 1, 1, 1, 0, 0, 0]);
+        assert_eq!(row(3), &[0, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(row(4), &[0, 0, 0, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn glider_round_trips() {
+        let pattern = Pattern::from_rle(GLIDER).unwrap();
+        let cells = pattern.centred_cells(8, 8);
+        let exported = Pattern::from_cells(&cells, 8, 8, pattern.rule);
+        assert_eq!(exported, pattern);
+        assert_eq!(exported.to_rle(), GLIDER);
+    }
+
+    #[test]
+    fn hexagonal_patterns_keep_their_shape() {
+        // The bottom row of the pattern lands on an odd row when centred, so the pattern is
+        // moved down to keep its rows shifted the same way
+        let rule: Rule = "B2/S34H".parse().unwrap();
+        let pattern = Pattern::from_rle("x = 2, y = 2, rule = B2/S34H\nbo$o!").unwrap();
+        let cells = pattern.starting_cells(rule, 8, 8, 1);
+        assert_eq!(cells[2 * 8 + 3], 1);
+        assert_eq!(cells[3 * 8 + 4], 1);
+
+        // Exporting a pattern that starts on an odd row takes in the even row below it
+        let mut cells = vec![0; 8 * 8];
+        cells[3 * 8 + 3] = 1;
+        cells[4 * 8 + 3] = 1;
+        let exported = Pattern::from_cells(&cells, 8, 8, Some(rule));
+        assert_eq!((exported.width, exported.height), (1, 3));
+        let reloaded = Pattern::from_rle(&exported.to_rle()).unwrap();
+        assert_eq!(reloaded.starting_cells(rule, 8, 8, 1), cells);
+    }
+
+    #[test]
+    fn triangular_patterns_keep_their_shape() {
+        // A row of an upward, a downward and an upward triangle, which would be turned the other
+        // way up if the pattern was simply centred
+        let rule: Rule = "B3/S23L".parse().unwrap();
+        let mut cells = vec![0; 8 * 8];
+        cells[3 * 8 + 3..3 * 8 + 6].fill(1);
+        let exported = Pattern::from_cells(&cells, 8, 8, Some(rule));
+        let reloaded = Pattern::from_rle(&exported.to_rle()).unwrap();
+        let points_up: Vec<_> = (0..8 * 8)
+            .filter(|&i| reloaded.starting_cells(rule, 8, 8, 1)[i] != 0)
+            .map(|i| (i % 8 + i / 8) % 2 == 0)
+            .collect();
+        assert_eq!(points_up, [true, false, true]);
+    }
+
+    #[test]
+    fn long_runs_are_rejected() {
+        assert_eq!(
+            Pattern::from_rle("x = 1, y = 1\n99999999999o!"),
+            Err(RleError::TooLarge)
+        );
+        assert_eq!(
+            Pattern::from_rle("x = 1, y = 1\n4097o!"),
+            Err(RleError::TooLarge)
+        );
+        assert_eq!(
+            Pattern::from_rle("x = 1, y = 1\n4096$o!"),
+            Err(RleError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn large_headers_are_rejected() {
+        assert_eq!(
+            Pattern::from_rle("x = 4097, y = 1\no!"),
+            Err(RleError::TooLarge)
+        );
+        assert_eq!(
+            Pattern::from_rle("x = 4000000000, y = 4000000000\no!"),
+            Err(RleError::TooLarge)
+        );
+    }
+}
@@ -0,0 +1,1209 @@
+// Cellular automaton rules.
+//
+// Life-like rules are written in B/S notation, e.g. B3/S23 for Conway's Game of Life.
+// The counts may be narrowed down to particular arrangements of live neighbours in Hensel
+// notation, e.g. B2-a/S12 (see hensel.rs). As in Golly, a rule ending in H is for the hexagonal
+// lattice, where cells have 6 neighbours, e.g. B2/S34H, and only counts are accepted (see
+// lattice.rs). Likewise a rule ending in L is for the triangular lattice, where cells have the 12
+// neighbours they share a corner with, e.g. B45/S3456L, with counts above 9 written as a, b and
+// c, and a rule ending in LE only counts the 3 neighbours sharing an edge, e.g. B1/S12LE. Rules
+// are compiled to a lookup table with an entry for each arrangement of a cell's neighbours,
+// which the compute shader indexes directly.
+//
+// Generations rules add dying states in B/S/C notation, e.g. B2/S/C3 for Brian's Brain. Cells
+// have C states: 0 is dead, 1 is alive, and a live cell that doesn't survive passes through the
+// dying states 2, 3, .. C-1 before it dies. Only live cells count as neighbours. Life-like
+// rules are Generations rules with 2 states.
+//
+// Larger than Life rules extend Life-like rules to larger neighbourhoods, in Golly's notation,
+// e.g. R5,C0,M1,S34..58,B34..45,NM for Bosco's rule: R is the radius of the neighbourhood, C the
+// number of states as for Generations rules (0 and 1 both mean 2 states), M1 counts the cell
+// itself as a neighbour, S and B are the ranges of neighbour counts for survival and birth, and
+// NM / NN choose a Moore (square) or von Neumann (diamond) neighbourhood.
+//
+// Lenia is a continuous automaton, written in the notation of Bert Chan's Lenia, e.g.
+// R=13;T=10;b=1;m=0.15;s=0.015;kn=1;gn=1 for Orbium. Each cell has a value from 0 to 1. The
+// potential of a cell is the weighted sum of the values around it, with weights from a kernel of
+// R cells radius made of concentric rings with heights b. Each generation adds growth(potential)
+// / T to the value, where the growth function peaks at m with width s. kn and gn choose the shape
+// of the kernel's rings and of the growth function: 1 polynomial, 2 exponential or 3 step. Cells
+// with a value above 0 are alive.
+//
+// Wireworld is written as WireWorld, as in Golly. Its states are numbered as in Golly too, so
+// that Golly's Wireworld patterns load as they are.
+//
+// Elementary cellular automata are written as W and Wolfram's rule number, e.g. W30 or W110, as
+// in Golly. Each generation is a single row, whose cells follow from the cell above them in time
+// and its two neighbours. Bit n of the rule number is the next state for the neighbourhood whose
+// left, middle and right cells are the bits of n, from most to least significant. The grid
+// shows the generations as a space-time diagram, with each generation below the one before
+// and the oldest scrolling off the top.
+//
+// 3D Life-like rules are written in Carter Bays' notation, e.g. 4555 or 5766: four digits
+// giving the inclusive ranges of live neighbour counts for which a cell survives, then for which
+// a dead cell is born. Cells are cubes with 26 neighbours, those sharing a face, edge or corner
+// with them, and the grid is a stack of layers.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::hensel::Transitions;
+use crate::lattice::Lattice;
+use crate::topology::Topology;
+
+// RLE files write each live state as a single letter from A to X
+pub const MAX_STATES: u32 = 25;
+// The largest Larger than Life neighbourhood. Must match LTL_MAX_RADIUS in compute.wgsl.
+pub const MAX_RADIUS: u32 = 10;
+// The largest Lenia kernel, whose weights take (2 * radius + 1)^2 floats. Must match
+// LENIA_MAX_RADIUS in compute.wgsl.
+pub const MAX_LENIA_RADIUS: u32 = 32;
+// The most rings a Lenia kernel can have
+pub const MAX_LENIA_RINGS: usize = 4;
+// A cube has this many neighbours in a 3D grid
+pub const LIFE_3D_NEIGHBOURS: u32 = 26;
+// The lookup table has an entry for each arrangement of the largest neighbourhood, the 12 cells
+// around a triangle
+pub(crate) const RULE_TABLE_SIZE: usize = 1 << 12;
+
+// Wireworld cell states. Electrons move along conductors as a head followed by a tail.
+pub const WIREWORLD_EMPTY: u32 = 0;
+pub const WIREWORLD_HEAD: u32 = 1;
+pub const WIREWORLD_TAIL: u32 = 2;
+pub const WIREWORLD_CONDUCTOR: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    // Life-like and Generations rules
+    Life(LifeRule),
+    LargerThanLife(LargerThanLifeRule),
+    Lenia(LeniaRule),
+    // Empty cells stay empty, electron heads become tails, tails become conductors, and
+    // conductors become heads if one or two of their neighbours are heads
+    Wireworld,
+    // A one dimensional rule, by its Wolfram number
+    Elementary(u8),
+    Life3D(Life3DRule),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LifeRule {
+    pub birth: Transitions,
+    pub survive: Transitions,
+    // The number of cell states, including dead, alive and any dying states
+    pub states: u32,
+    pub neighbourhood: LifeNeighbourhood,
+}
+
+// The neighbours of a cell under a Life-like or Generations rule
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LifeNeighbourhood {
+    // The 8 cells around the cell on the square lattice
+    Moore,
+    // The 6 cells around the cell on the hexagonal lattice
+    Hexagonal,
+    // The 12 cells sharing a corner with the cell on the triangular lattice
+    Triangular,
+    // The 3 cells sharing an edge with the cell on the triangular lattice
+    TriangularEdges,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LargerThanLifeRule {
+    // How far the neighbourhood reaches from the cell, from 1 to MAX_RADIUS
+    pub radius: u32,
+    pub neighbourhood: Neighbourhood,
+    // Whether the cell counts itself as one of its neighbours
+    pub middle: bool,
+    // The inclusive ranges of live neighbour counts for which a cell survives / is born
+    pub survive: (u32, u32),
+    pub birth: (u32, u32),
+    // The number of cell states, including dead, alive and any dying states
+    pub states: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Life3DRule {
+    // The live neighbour counts, out of 26, for which a cell survives / is born, with a bit set
+    // for each count
+    pub survive: u32,
+    pub birth: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LeniaRule {
+    // How far the kernel reaches from the cell, from 1 to MAX_LENIA_RADIUS
+    pub radius: u32,
+    // How many generations make up one unit of time. Each generation adds 1/time_steps of the
+    // growth to a cell's value.
+    pub time_steps: u32,
+    // The peak heights of the kernel's concentric rings, from the centre outwards. Only the first
+    // rings are used.
+    pub ring_heights: [f32; MAX_LENIA_RINGS],
+    pub rings: usize,
+    pub kernel: LeniaFunction,
+    // The potential at which growth peaks, and how quickly it falls away either side
+    pub growth_centre: f32,
+    pub growth_width: f32,
+    pub growth: LeniaFunction,
+}
+
+// The shape of a Lenia kernel's rings, or of its growth function, numbered as in Lenia
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeniaFunction {
+    Polynomial = 1,
+    Exponential = 2,
+    Step = 3,
+}
+
+// Which of the cells around a cell are its neighbours
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Neighbourhood {
+    // The square of cells around the cell
+    Moore,
+    // The diamond of cells within the radius, counting steps horizontally and vertically
+    VonNeumann,
+}
+
+impl Rule {
+    pub const CONWAY: Rule = Rule::Life(LifeRule::CONWAY);
+
+    // The number of cell states, including dead
+    pub fn states(&self) -> u32 {
+        match self {
+            Rule::Life(rule) => rule.states,
+            Rule::LargerThanLife(rule) => rule.states,
+            Rule::Lenia(_) | Rule::Elementary(_) | Rule::Life3D(_) => 2,
+            Rule::Wireworld => 4,
+        }
+    }
+
+    // The shape and arrangement of the cells
+    pub fn lattice(&self) -> Lattice {
+        match self {
+            Rule::Life(rule) => match rule.neighbourhood {
+                LifeNeighbourhood::Moore => Lattice::Square,
+                LifeNeighbourhood::Hexagonal => Lattice::Hexagonal,
+                LifeNeighbourhood::Triangular | LifeNeighbourhood::TriangularEdges => {
+                    Lattice::Triangular
+                }
+            },
+            Rule::LargerThanLife(_)
+            | Rule::Lenia(_)
+            | Rule::Wireworld
+            | Rule::Elementary(_)
+            | Rule::Life3D(_) => Lattice::Square,
+        }
+    }
+
+    // Whether cells have a continuous value as well as a state
+    pub fn is_continuous(&self) -> bool {
+        matches!(self, Rule::Lenia(_))
+    }
+
+    // Whether each generation is a single row of the grid, which is drawn as a history of
+    // the generations
+    pub fn is_one_dimensional(&self) -> bool {
+        matches!(self, Rule::Elementary(_))
+    }
+
+    // Whether the grid is a stack of layers of cubes, which is drawn in perspective
+    pub fn is_three_dimensional(&self) -> bool {
+        matches!(self, Rule::Life3D(_))
+    }
+
+    // Whether the rule's grid can have a topology. The layers of a 3D grid wrap around as a
+    // torus's edges do, so 3D grids are either bounded or a torus in all three directions.
+    pub fn supports_topology(&self, topology: Topology) -> bool {
+        let layers_fit =
+            !self.is_three_dimensional() || matches!(topology, Topology::Bounded | Topology::Torus);
+        layers_fit && self.lattice().supports_topology(topology)
+    }
+
+    // The state a new cell is drawn with, until another is chosen
+    pub fn default_brush(&self) -> u32 {
+        match self {
+            Rule::Life(_)
+            | Rule::LargerThanLife(_)
+            | Rule::Lenia(_)
+            | Rule::Elementary(_)
+            | Rule::Life3D(_) => 1,
+            Rule::Wireworld => WIREWORLD_CONDUCTOR,
+        }
+    }
+
+    // A name for each state, for logging
+    pub(crate) fn state_name(&self, state: u32) -> &'static str {
+        match (self, state) {
+            (Rule::Wireworld, WIREWORLD_HEAD) => "electron head",
+            (Rule::Wireworld, WIREWORLD_TAIL) => "electron tail",
+            (Rule::Wireworld, WIREWORLD_CONDUCTOR) => "conductor",
+            (Rule::Wireworld, _) => "empty",
+            (_, 0) => "dead",
+            (_, 1) => "alive",
+            (_, _) => "dying",
+        }
+    }
+
+    // The state of a cell in a randomised grid. Life-like cells are dead or alive. 3D cells
+    // have so many neighbours that half of them being alive would kill them all, so they're only
+    // alive as often as gives a cell the middle of the counts it needs to be born. Wireworld
+    // grids are a tangle of conductors with electrons scattered along them. Lenia's states
+    // follow from random_value.
+    pub(crate) fn random_state(&self, rng: &mut impl rand::Rng) -> u32 {
+        match self {
+            Rule::Life(_) | Rule::LargerThanLife(_) | Rule::Lenia(_) | Rule::Elementary(_) => {
+                rng.gen_range(0..=1)
+            }
+            Rule::Life3D(rule) => {
+                let neighbours = 2 * LIFE_3D_NEIGHBOURS;
+                let (fewest, most) = rule.birth_range().unwrap_or((0, 0));
+                rng.gen_ratio((fewest + most).min(neighbours), neighbours) as u32
+            }
+            Rule::Wireworld => match rng.gen_range(0..16) {
+                0 => WIREWORLD_HEAD,
+                1..=7 => WIREWORLD_CONDUCTOR,
+                _ => WIREWORLD_EMPTY,
+            },
+        }
+    }
+
+    // The value of a cell in a randomised grid of a continuous rule. A third of the cells have a
+    // random value, which is dense enough for most Lenia rules to grow rather than die out.
+    pub(crate) fn random_value(&self, rng: &mut impl rand::Rng) -> f32 {
+        if rng.gen_ratio(1, 3) {
+            rng.gen()
+        } else {
+            0.0
+        }
+    }
+
+    // The lookup table the compute shader indexes with the arrangement of a cell's active
+    // neighbours. Only Life-like, Generations and elementary rules use it.
+    pub(crate) fn lookup_table(&self) -> Vec<u32> {
+        match self {
+            Rule::Life(rule) => rule.lookup_table(),
+            // The next state for each arrangement of the left, middle and right cells
+            Rule::Elementary(number) => (0..8).map(|n| (*number as u32 >> n) & 1).collect(),
+            Rule::LargerThanLife(_) | Rule::Lenia(_) | Rule::Wireworld | Rule::Life3D(_) => {
+                Vec::new()
+            }
+        }
+    }
+
+    // The Lenia kernel's weights, which the compute shader reads from a storage buffer. Other
+    // rules have none.
+    pub(crate) fn kernel_weights(&self) -> Vec<f32> {
+        match self {
+            Rule::Lenia(rule) => rule.kernel_weights(),
+            Rule::Life(_)
+            | Rule::LargerThanLife(_)
+            | Rule::Wireworld
+            | Rule::Elementary(_)
+            | Rule::Life3D(_) => Vec::new(),
+        }
+    }
+}
+
+impl LifeRule {
+    pub const CONWAY: LifeRule = LifeRule {
+        birth: Transitions::totalistic(&[3]),
+        survive: Transitions::totalistic(&[2, 3]),
+        states: 2,
+        neighbourhood: LifeNeighbourhood::Moore,
+    };
+
+    // For each arrangement of live neighbours, bit 0 is set if a dead cell is born and bit 1 if
+    // a live cell survives
+    pub(crate) fn lookup_table(&self) -> Vec<u32> {
+        (0..1u32 << self.neighbourhood.size())
+            .map(|neighbourhood| {
+                let (born, survives) = match self.neighbourhood {
+                    LifeNeighbourhood::Moore => (
+                        self.birth.contains(neighbourhood as u8),
+                        self.survive.contains(neighbourhood as u8),
+                    ),
+                    LifeNeighbourhood::Hexagonal
+                    | LifeNeighbourhood::Triangular
+                    | LifeNeighbourhood::TriangularEdges => {
+                        let count = neighbourhood.count_ones();
+                        (
+                            self.birth.contains_count(count),
+                            self.survive.contains_count(count),
+                        )
+                    }
+                };
+                born as u32 | (survives as u32) << 1
+            })
+            .collect()
+    }
+}
+
+impl LifeNeighbourhood {
+    // How many neighbours a cell has
+    pub fn size(&self) -> u32 {
+        match self {
+            LifeNeighbourhood::Moore => 8,
+            LifeNeighbourhood::Hexagonal => 6,
+            LifeNeighbourhood::Triangular => 12,
+            LifeNeighbourhood::TriangularEdges => 3,
+        }
+    }
+
+    // The suffix that follows the rest of a rule to choose the neighbourhood
+    fn suffix(&self) -> &'static str {
+        match self {
+            LifeNeighbourhood::Moore => "",
+            LifeNeighbourhood::Hexagonal => "H",
+            LifeNeighbourhood::Triangular => "L",
+            LifeNeighbourhood::TriangularEdges => "LE",
+        }
+    }
+}
+
+impl LeniaRule {
+    // Orbium, the glider-like creature most often shown off in Lenia
+    pub const ORBIUM: LeniaRule = LeniaRule {
+        radius: 13,
+        time_steps: 10,
+        ring_heights: [1.0, 0.0, 0.0, 0.0],
+        rings: 1,
+        kernel: LeniaFunction::Polynomial,
+        growth_centre: 0.15,
+        growth_width: 0.015,
+        growth: LeniaFunction::Polynomial,
+    };
+
+    // The weight of each cell in the (2 * radius + 1)^2 square around a cell, in row-major order
+    // from the bottom left. The weights add up to 1, so the potential is a weighted average.
+    pub(crate) fn kernel_weights(&self) -> Vec<f32> {
+        let radius = self.radius as i32;
+        let mut weights: Vec<f32> = (-radius..=radius)
+            .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+            .map(|(dx, dy)| {
+                // The distance from the cell as a fraction of the radius, scaled so that each
+                // ring spans 1
+                let distance = ((dx * dx + dy * dy) as f32).sqrt() / radius as f32;
+                if distance >= 1.0 {
+                    return 0.0;
+                }
+                let position = distance * self.rings as f32;
+                let ring = position as usize;
+                self.ring_heights[ring] * self.kernel_core(position.fract())
+            })
+            .collect();
+
+        let total: f32 = weights.iter().sum();
+        if total > 0.0 {
+            weights.iter_mut().for_each(|weight| *weight /= total);
+        }
+        weights
+    }
+
+    // The shape of each ring, across its width from 0 to 1
+    fn kernel_core(&self, r: f32) -> f32 {
+        match self.kernel {
+            LeniaFunction::Polynomial => (4.0 * r * (1.0 - r)).powi(4),
+            LeniaFunction::Exponential if r > 0.0 && r < 1.0 => (4.0 - 1.0 / (r * (1.0 - r))).exp(),
+            LeniaFunction::Exponential => 0.0,
+            LeniaFunction::Step => (0.25..=0.75).contains(&r) as u32 as f32,
+        }
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self::CONWAY
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleParseError {
+    // The rulestring isn't of the form B<digits>/S<digits>, and doesn't name another rule
+    InvalidFormat,
+    // A neighbour count outside of 0 to the number of neighbours
+    InvalidNeighbourCount(char, u32),
+    // The same neighbour count appears twice in one section
+    DuplicateNeighbourCount(char),
+    // A letter that isn't in Hensel notation for the neighbour count it follows
+    InvalidLetter(char, u32),
+    // The number of states of a Generations rule is outside of 2..=MAX_STATES
+    InvalidStateCount(String),
+    // The radius of a Larger than Life rule is outside of 1..=MAX_RADIUS
+    InvalidRadius(String),
+    // A Larger than Life neighbour count range isn't of the form <min>..<max>
+    InvalidRange(String),
+    // A Larger than Life neighbourhood other than NM or NN
+    InvalidNeighbourhood(String),
+    // A Lenia field that's missing its value, unknown, repeated or out of range
+    InvalidLeniaField(String),
+    // An elementary rule number outside of 0..=255
+    InvalidElementaryRule(String),
+    // A 3D rule whose ranges run backwards, e.g. 5455
+    Invalid3DRule(String),
+}
+
+impl fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleParseError::InvalidFormat => {
+                write!(
+                    f,
+                    "expected a rulestring such as B3/S23, B2/S/C3, R5,C0,M1,S34..58,B34..45,NM, \
+                     R=13;T=10;b=1;m=0.15;s=0.015, Lenia, WireWorld, W30 or 4555"
+                )
+            }
+            RuleParseError::InvalidNeighbourCount(c, max) => {
+                write!(f, "'{c}' is not a neighbour count between 0 and {max}")
+            }
+            RuleParseError::DuplicateNeighbourCount(c) => {
+                write!(f, "neighbour count '{c}' appears more than once")
+            }
+            RuleParseError::InvalidLetter(letter, count) => {
+                write!(
+                    f,
+                    "'{letter}' is not a Hensel letter for {count} neighbours"
+                )
+            }
+            RuleParseError::InvalidStateCount(states) => {
+                write!(
+                    f,
+                    "'{states}' is not a number of states between 2 and {MAX_STATES}"
+                )
+            }
+            RuleParseError::InvalidRadius(radius) => {
+                write!(f, "'{radius}' is not a radius between 1 and {MAX_RADIUS}")
+            }
+            RuleParseError::InvalidRange(range) => {
+                write!(
+                    f,
+                    "invalid range '{range}', expected <min>..<max>, e.g. 34..58"
+                )
+            }
+            RuleParseError::InvalidNeighbourhood(neighbourhood) => {
+                write!(
+                    f,
+                    "unknown neighbourhood '{neighbourhood}', expected NM or NN"
+                )
+            }
+            RuleParseError::InvalidLeniaField(field) => {
+                write!(
+                    f,
+                    "invalid Lenia field '{field}', expected e.g. \
+                     R=13;T=10;b=1;m=0.15;s=0.015;kn=1;gn=1"
+                )
+            }
+            RuleParseError::InvalidElementaryRule(number) => {
+                write!(f, "'{number}' is not an elementary rule between 0 and 255")
+            }
+            RuleParseError::Invalid3DRule(rule) => write!(
+                f,
+                "invalid 3D rule '{rule}', each range's minimum must come first and each count \
+                 must be one of 0 to {LIFE_3D_NEIGHBOURS} given once"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RuleParseError {}
+
+impl FromStr for Rule {
+    type Err = RuleParseError;
+
+    // Accepts WireWorld (case-insensitive), Lenia (case-insensitive) for Orbium, a Lenia rule,
+    // a Larger than Life rule, an elementary rule, a 3D rule, or a Life-like or Generations rule
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("wireworld") {
+            return Ok(Rule::Wireworld);
+        }
+        // Elementary rules are the only ones starting with W
+        if let Some(number) = s.strip_prefix(['W', 'w']) {
+            return number
+                .parse()
+                .map(Rule::Elementary)
+                .map_err(|_| RuleParseError::InvalidElementaryRule(number.to_string()));
+        }
+        if s.eq_ignore_ascii_case("lenia") {
+            return Ok(Rule::Lenia(LeniaRule::ORBIUM));
+        }
+        // Lenia rules are the only ones made of key=value fields
+        if s.contains('=') {
+            return s.parse().map(Rule::Lenia);
+        }
+        // 3D rules are the only ones made of digits alone, or of comma separated counts in
+        // sections separated by a slash
+        let digits_alone = !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+        if digits_alone || (s.contains(',') && s.contains('/')) {
+            return s.parse().map(Rule::Life3D);
+        }
+        // Larger than Life rules are the only other ones made of comma separated fields
+        if s.contains(',') {
+            return s.parse().map(Rule::LargerThanLife);
+        }
+        s.parse().map(Rule::Life)
+    }
+}
+
+impl FromStr for LifeRule {
+    type Err = RuleParseError;
+
+    // Accepts B/S notation in any order (B36/S23, S23/B36), case-insensitive,
+    // as well as the traditional S/B notation without letters (23/36).
+    // Generations rules add a third section with the number of states, either as B/S/C in any
+    // order (B2/S345/C4) or as S/B/C without letters (345/2/4).
+    // A trailing H makes the rule hexagonal (B2/S34H, 34/2/3H), and a trailing L or LE makes it
+    // triangular (B45/S3456L, B1/S12LE).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let upper = s.to_ascii_uppercase();
+        let neighbourhood = [
+            LifeNeighbourhood::TriangularEdges,
+            LifeNeighbourhood::Triangular,
+            LifeNeighbourhood::Hexagonal,
+        ]
+        .into_iter()
+        .find(|neighbourhood| upper.ends_with(neighbourhood.suffix()))
+        .unwrap_or(LifeNeighbourhood::Moore);
+        let s = &s[..s.len() - neighbourhood.suffix().len()];
+        let sections: Vec<&str> = s.split('/').collect();
+        if !(2..=3).contains(&sections.len()) {
+            return Err(RuleParseError::InvalidFormat);
+        }
+
+        // The letters a, b and c are also neighbour counts on the triangular lattice, so a
+        // leading letter can't tell the two notations apart. Only B/S notation has an S section,
+        // and in it every section starts with its prefix.
+        let named = sections
+            .iter()
+            .any(|section| section.starts_with(|c: char| c.eq_ignore_ascii_case(&'s')));
+        let split_prefix = |section: &str| -> (Option<char>, String) {
+            let mut chars = section.chars();
+            match chars.next() {
+                Some(c) if named => (Some(c.to_ascii_uppercase()), chars.collect()),
+                _ => (None, section.to_string()),
+            }
+        };
+
+        let sections: Vec<(Option<char>, String)> =
+            sections.into_iter().map(split_prefix).collect();
+
+        let (birth, survive, states) = if !named {
+            // S/B or S/B/C
+            let states = sections.get(2).map(|(_, states)| states.clone());
+            (sections[1].1.clone(), sections[0].1.clone(), states)
+        } else {
+            // Each section is named by its prefix, and may come in any order
+            let mut birth = None;
+            let mut survive = None;
+            let mut states = None;
+            for (prefix, section) in sections {
+                let slot = match prefix {
+                    Some('B') => &mut birth,
+                    Some('S') => &mut survive,
+                    Some('C') | Some('G') => &mut states,
+                    _ => return Err(RuleParseError::InvalidFormat),
+                };
+                if slot.replace(section).is_some() {
+                    return Err(RuleParseError::InvalidFormat);
+                }
+            }
+            match (birth, survive) {
+                (Some(birth), Some(survive)) => (birth, survive, states),
+                _ => return Err(RuleParseError::InvalidFormat),
+            }
+        };
+
+        let states = match states {
+            Some(states) => states
+                .parse()
+                .ok()
+                .filter(|states| (2..=MAX_STATES).contains(states))
+                .ok_or(RuleParseError::InvalidStateCount(states))?,
+            None => 2,
+        };
+
+        let parse_transitions = |section: &str| match neighbourhood {
+            LifeNeighbourhood::Moore => section.parse(),
+            _ => Transitions::parse_totalistic(section, neighbourhood.size()),
+        };
+
+        Ok(LifeRule {
+            birth: parse_transitions(&birth)?,
+            survive: parse_transitions(&survive)?,
+            states,
+            neighbourhood,
+        })
+    }
+}
+
+impl FromStr for LargerThanLifeRule {
+    type Err = RuleParseError;
+
+    // Accepts Golly's notation, case-insensitive. The fields may come in any order, and all
+    // but the radius and the ranges are optional: by default there are 2 states, the cell
+    // doesn't count itself, and the neighbourhood is a Moore neighbourhood.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut radius = None;
+        let mut states = None;
+        let mut middle = None;
+        let mut survive = None;
+        let mut birth = None;
+        let mut neighbourhood = None;
+
+        for field in s.trim().split(',') {
+            let field = field.trim();
+            let mut chars = field.chars();
+            let prefix = chars.next().map(|c| c.to_ascii_uppercase());
+            let value = chars.as_str().to_string();
+            let slot = match prefix {
+                Some('R') => &mut radius,
+                Some('C') => &mut states,
+                Some('M') => &mut middle,
+                Some('S') => &mut survive,
+                Some('B') => &mut birth,
+                Some('N') => &mut neighbourhood,
+                _ => return Err(RuleParseError::InvalidFormat),
+            };
+            if slot.replace(value).is_some() {
+                return Err(RuleParseError::InvalidFormat);
+            }
+        }
+
+        let (Some(radius), Some(survive), Some(birth)) = (radius, survive, birth) else {
+            return Err(RuleParseError::InvalidFormat);
+        };
+        let radius = radius
+            .parse()
+            .ok()
+            .filter(|radius| (1..=MAX_RADIUS).contains(radius))
+            .ok_or(RuleParseError::InvalidRadius(radius))?;
+
+        let states = match states {
+            Some(states) => match states.parse() {
+                // Golly writes rules with 2 states as C0, and accepts C1 too
+                Ok(0 | 1) => 2,
+                Ok(count) if count <= MAX_STATES => count,
+                _ => return Err(RuleParseError::InvalidStateCount(states)),
+            },
+            None => 2,
+        };
+
+        let middle = match middle.as_deref() {
+            None | Some("0") => false,
+            Some("1") => true,
+            Some(_) => return Err(RuleParseError::InvalidFormat),
+        };
+
+        let neighbourhood = match neighbourhood.map(|n| n.to_ascii_uppercase()).as_deref() {
+            None | Some("M") => Neighbourhood::Moore,
+            Some("N") => Neighbourhood::VonNeumann,
+            Some(other) => return Err(RuleParseError::InvalidNeighbourhood(format!("N{other}"))),
+        };
+
+        Ok(LargerThanLifeRule {
+            radius,
+            neighbourhood,
+            middle,
+            survive: parse_range(&survive)?,
+            birth: parse_range(&birth)?,
+            states,
+        })
+    }
+}
+
+impl FromStr for LeniaRule {
+    type Err = RuleParseError;
+
+    // Accepts Lenia's notation. The fields may come in any order, and all but the radius and the
+    // growth function's centre and width are optional: by default T=10, b=1, kn=1 and gn=1.
+    // Ring heights may be written as fractions, e.g. b=1/2,1.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rule = LeniaRule {
+            radius: 0,
+            growth_centre: f32::NAN,
+            growth_width: f32::NAN,
+            ..LeniaRule::ORBIUM
+        };
+        let mut seen = Vec::new();
+
+        for field in s
+            .trim()
+            .split(';')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+        {
+            let invalid = || RuleParseError::InvalidLeniaField(field.to_string());
+            let (key, value) = field.split_once('=').ok_or_else(invalid)?;
+            let (key, value) = (key.trim(), value.trim());
+            if seen.contains(&key) {
+                return Err(invalid());
+            }
+            seen.push(key);
+
+            match key {
+                "R" => {
+                    rule.radius = value
+                        .parse()
+                        .ok()
+                        .filter(|radius| (1..=MAX_LENIA_RADIUS).contains(radius))
+                        .ok_or_else(invalid)?;
+                }
+                "T" => {
+                    rule.time_steps = value.parse().ok().filter(|&t| t > 0).ok_or_else(invalid)?;
+                }
+                "b" => {
+                    let heights = value
+                        .split(',')
+                        .map(parse_fraction)
+                        .collect::<Option<Vec<f32>>>()
+                        .filter(|heights| (1..=MAX_LENIA_RINGS).contains(&heights.len()))
+                        .ok_or_else(invalid)?;
+                    rule.ring_heights = [0.0; MAX_LENIA_RINGS];
+                    rule.ring_heights[..heights.len()].copy_from_slice(&heights);
+                    rule.rings = heights.len();
+                }
+                "m" => rule.growth_centre = value.parse().map_err(|_| invalid())?,
+                "s" => {
+                    rule.growth_width = value
+                        .parse()
+                        .ok()
+                        .filter(|&s: &f32| s > 0.0)
+                        .ok_or_else(invalid)?;
+                }
+                "kn" => rule.kernel = parse_lenia_function(value).ok_or_else(invalid)?,
+                "gn" => rule.growth = parse_lenia_function(value).ok_or_else(invalid)?,
+                _ => return Err(invalid()),
+            }
+        }
+
+        if rule.radius == 0 || rule.growth_centre.is_nan() || rule.growth_width.is_nan() {
+            return Err(RuleParseError::InvalidFormat);
+        }
+        Ok(rule)
+    }
+}
+
+impl FromStr for Life3DRule {
+    type Err = RuleParseError;
+
+    // Accepts Bays' notation: four digits, the minimum and maximum counts for survival then
+    // for birth, e.g. 4555. Any counts can be given separated by commas instead, either as S/B
+    // without letters (4,5/5) or as B/S in any order, case-insensitive (B5/S4,5). The comma tells
+    // these apart from 2D rules, so a rule with no more than one count for each needs a
+    // trailing comma, e.g. B13/S12,.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || RuleParseError::Invalid3DRule(s.to_string());
+        if !s.contains(',') {
+            let digits: Option<Vec<u32>> = s.chars().map(|c| c.to_digit(10)).collect();
+            let Some([survive_min, survive_max, birth_min, birth_max]) = digits.as_deref() else {
+                return Err(RuleParseError::InvalidFormat);
+            };
+            if survive_min > survive_max || birth_min > birth_max {
+                return Err(invalid());
+            }
+            return Ok(Life3DRule {
+                survive: Self::counts_between(*survive_min, *survive_max),
+                birth: Self::counts_between(*birth_min, *birth_max),
+            });
+        }
+
+        let split_prefix = |section: &str| {
+            let mut chars = section.chars();
+            match chars.next().map(|c| c.to_ascii_uppercase()) {
+                Some(prefix @ ('B' | 'S')) => (Some(prefix), chars.as_str().to_string()),
+                _ => (None, section.to_string()),
+            }
+        };
+        let sections: Vec<_> = s.split('/').map(split_prefix).collect();
+        let (survive, birth) = match &sections[..] {
+            [(None, survive), (None, birth)]
+            | [(Some('S'), survive), (Some('B'), birth)]
+            | [(Some('B'), birth), (Some('S'), survive)] => (survive, birth),
+            _ => return Err(RuleParseError::InvalidFormat),
+        };
+
+        let parse_counts = |section: &str| {
+            let counts = section
+                .split(',')
+                .map(str::trim)
+                .filter(|count| !count.is_empty());
+            counts.into_iter().try_fold(0, |mask: u32, count| {
+                let count = count
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&count| count <= LIFE_3D_NEIGHBOURS && mask & (1 << count) == 0)
+                    .ok_or_else(invalid)?;
+                Ok(mask | 1 << count)
+            })
+        };
+        Ok(Life3DRule {
+            survive: parse_counts(survive)?,
+            birth: parse_counts(birth)?,
+        })
+    }
+}
+
+impl Life3DRule {
+    // A mask of the counts from min to max inclusive
+    fn counts_between(min: u32, max: u32) -> u32 {
+        (u32::MAX >> (31 - max)) & (u32::MAX << min)
+    }
+
+    // The fewest and most neighbours a cell can be born with, or None if it can't be born
+    pub(crate) fn birth_range(&self) -> Option<(u32, u32)> {
+        Self::range(self.birth)
+    }
+
+    fn range(counts: u32) -> Option<(u32, u32)> {
+        (counts != 0).then(|| (counts.trailing_zeros(), 31 - counts.leading_zeros()))
+    }
+
+    // The counts as a range in Bays' notation, if they're a single digit range
+    fn bays_range(counts: u32) -> Option<(u32, u32)> {
+        Self::range(counts)
+            .filter(|&(min, max)| max <= 9 && counts == Self::counts_between(min, max))
+    }
+}
+
+// Parse a number such as 0.5 or a fraction such as 1/2
+fn parse_fraction(value: &str) -> Option<f32> {
+    let value = value.trim();
+    match value.split_once('/') {
+        Some((numerator, denominator)) => {
+            let numerator: f32 = numerator.trim().parse().ok()?;
+            let denominator: f32 = denominator.trim().parse().ok()?;
+            (denominator != 0.0).then(|| numerator / denominator)
+        }
+        None => value.parse().ok(),
+    }
+    .filter(|value: &f32| value.is_finite() && *value >= 0.0)
+}
+
+fn parse_lenia_function(value: &str) -> Option<LeniaFunction> {
+    match value {
+        "1" => Some(LeniaFunction::Polynomial),
+        "2" => Some(LeniaFunction::Exponential),
+        "3" => Some(LeniaFunction::Step),
+        _ => None,
+    }
+}
+
+// Parse an inclusive range of neighbour counts, e.g. 34..58
+fn parse_range(range: &str) -> Result<(u32, u32), RuleParseError> {
+    let invalid = || RuleParseError::InvalidRange(range.to_string());
+    let (min, max) = range.split_once("..").ok_or_else(invalid)?;
+    let min = min.trim().parse::<u32>().map_err(|_| invalid())?;
+    let max = max.trim().parse::<u32>().map_err(|_| invalid())?;
+    if min > max {
+        return Err(invalid());
+    }
+    Ok((min, max))
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rule::Life(rule) => write!(f, "{rule}"),
+            Rule::LargerThanLife(rule) => write!(f, "{rule}"),
+            Rule::Lenia(rule) => write!(f, "{rule}"),
+            Rule::Wireworld => write!(f, "WireWorld"),
+            Rule::Elementary(number) => write!(f, "W{number}"),
+            Rule::Life3D(rule) => write!(f, "{rule}"),
+        }
+    }
+}
+
+impl fmt::Display for LifeRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "B{}/S{}", self.birth, self.survive)?;
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        write!(f, "{}", self.neighbourhood.suffix())
+    }
+}
+
+// In Bays' notation where it can be, e.g. 4555, and otherwise with the counts separated by
+// commas in B/S notation, e.g. B5,7/S4,5
+impl fmt::Display for Life3DRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let (Some(survive), Some(birth)) =
+            (Self::bays_range(self.survive), Self::bays_range(self.birth))
+        {
+            return write!(f, "{}{}{}{}", survive.0, survive.1, birth.0, birth.1);
+        }
+        let counts = |mask: u32| {
+            let counts = (0..=LIFE_3D_NEIGHBOURS).filter(|count| mask & (1 << count) != 0);
+            counts
+                .map(|count| count.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let (birth, survive) = (counts(self.birth), counts(self.survive));
+        // Without a comma the rule would be read as a 2D rule
+        let marker = if birth.contains(',') || survive.contains(',') {
+            ""
+        } else {
+            ","
+        };
+        write!(f, "B{birth}/S{survive}{marker}")
+    }
+}
+
+// In Golly's canonical form, e.g. R5,C0,M1,S34..58,B34..45,NM
+impl fmt::Display for LargerThanLifeRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let states = if self.states == 2 { 0 } else { self.states };
+        let neighbourhood = match self.neighbourhood {
+            Neighbourhood::Moore => 'M',
+            Neighbourhood::VonNeumann => 'N',
+        };
+        write!(
+            f,
+            "R{},C{},M{},S{}..{},B{}..{},N{}",
+            self.radius,
+            states,
+            self.middle as u32,
+            self.survive.0,
+            self.survive.1,
+            self.birth.0,
+            self.birth.1,
+            neighbourhood
+        )
+    }
+}
+
+// In Lenia's notation, with every field, e.g. R=13;T=10;b=1;m=0.15;s=0.015;kn=1;gn=1
+impl fmt::Display for LeniaRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let heights: Vec<String> = self.ring_heights[..self.rings]
+            .iter()
+            .map(|height| height.to_string())
+            .collect();
+        write!(
+            f,
+            "R={};T={};b={};m={};s={};kn={};gn={}",
+            self.radius,
+            self.time_steps,
+            heights.join(","),
+            self.growth_centre,
+            self.growth_width,
+            self.kernel as u32,
+            self.growth as u32
+        )
+    }
+}
+
+// The rule as it's laid out in the shader's uniform buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct RuleUniform {
+    kind: u32,
+    states: u32,
+    lattice: u32,
+    // Larger than Life rules only
+    radius: u32,
+    neighbourhood: u32,
+    middle: u32,
+    // Lenia rules only, along with the radius
+    growth: u32,
+    // 3D rules only, as masks with a bit for each count
+    birth_counts: u32,
+    // Larger than Life rules only
+    birth_range: [u32; 2],
+    survive_range: [u32; 2],
+    growth_centre: f32,
+    growth_width: f32,
+    time_step: f32,
+    survive_counts: u32,
+}
+
+impl From<Rule> for RuleUniform {
+    fn from(rule: Rule) -> Self {
+        let mut uniform = Self {
+            kind: 0,
+            states: rule.states(),
+            lattice: rule.lattice() as u32,
+            radius: 0,
+            neighbourhood: 0,
+            middle: 0,
+            growth: 0,
+            birth_counts: 0,
+            birth_range: [0; 2],
+            survive_range: [0; 2],
+            growth_centre: 0.0,
+            growth_width: 0.0,
+            time_step: 0.0,
+            survive_counts: 0,
+        };
+        // Must match the RULE_KIND_ and LATTICE_ constants in shader.wgsl, and the NEIGHBOURHOOD_
+        // and GROWTH_ constants in compute.wgsl
+        match rule {
+            Rule::Life(rule) => {
+                uniform.neighbourhood = match rule.neighbourhood {
+                    LifeNeighbourhood::Moore => 0,
+                    LifeNeighbourhood::Hexagonal => 2,
+                    LifeNeighbourhood::Triangular => 3,
+                    LifeNeighbourhood::TriangularEdges => 4,
+                };
+            }
+            Rule::Wireworld => uniform.kind = 1,
+            Rule::LargerThanLife(rule) => {
+                uniform.kind = 2;
+                uniform.radius = rule.radius;
+                uniform.neighbourhood = match rule.neighbourhood {
+                    Neighbourhood::Moore => 0,
+                    Neighbourhood::VonNeumann => 1,
+                };
+                uniform.middle = rule.middle as u32;
+                uniform.birth_range = [rule.birth.0, rule.birth.1];
+                uniform.survive_range = [rule.survive.0, rule.survive.1];
+            }
+            Rule::Lenia(rule) => {
+                uniform.kind = 3;
+                uniform.radius = rule.radius;
+                uniform.growth = rule.growth as u32;
+                uniform.growth_centre = rule.growth_centre;
+                uniform.growth_width = rule.growth_width;
+                uniform.time_step = 1.0 / rule.time_steps as f32;
+            }
+            Rule::Elementary(_) => uniform.kind = 4,
+            Rule::Life3D(rule) => {
+                uniform.kind = 5;
+                uniform.birth_counts = rule.birth;
+                uniform.survive_counts = rule.survive;
+            }
+        }
+        uniform
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(s: &str) -> Rule {
+        s.parse()
+            .unwrap_or_else(|e| panic!("'{s}' should parse: {e}"))
+    }
+
+    #[test]
+    fn rulestrings_round_trip() {
+        for s in [
+            "B3/S23",
+            "B36/S23",
+            "B/S012345678",
+            "B2-a/S12",
+            "B2ce3-jq/S1e2-kn4w",
+            "B2/S34H",
+            "B45/S3456L",
+            "B1/S12LE",
+            "B2/S/C3",
+            "R5,C0,M1,S34..58,B34..45,NM",
+            "R2,C3,M0,S1..2,B3..3,NN",
+            "R=13;T=10;b=1;m=0.15;s=0.015;kn=1;gn=1",
+            "W30",
+            "WireWorld",
+            "4555",
+            "5766",
+            "B5,7/S4,5",
+            "B14,15,16/S12,13",
+            "B13/S12,",
+            "B/S,",
+        ] {
+            assert_eq!(rule(s).to_string(), s);
+            assert_eq!(rule(&rule(s).to_string()), rule(s));
+        }
+    }
+
+    #[test]
+    fn other_notations_are_displayed_canonically() {
+        assert_eq!(rule("b3/s23").to_string(), "B3/S23");
+        assert_eq!(rule("S23/B3").to_string(), "B3/S23");
+        assert_eq!(rule("23/3").to_string(), "B3/S23");
+        assert_eq!(rule("23/3/2").to_string(), "B3/S23");
+        assert_eq!(
+            rule("Lenia").to_string(),
+            "R=13;T=10;b=1;m=0.15;s=0.015;kn=1;gn=1"
+        );
+        assert_eq!(rule("4,5/5").to_string(), "4555");
+        assert_eq!(rule("B5/S4,5").to_string(), "4555");
+        assert_eq!(rule("s4, 5/b5,").to_string(), "4555");
+        assert_eq!(rule("12,13/14").to_string(), "B14/S12,13");
+    }
+
+    #[test]
+    fn three_dimensional_rules_are_masks_of_counts() {
+        let Rule::Life3D(separated) = rule("B5,7/S4,5,26") else {
+            panic!("should be a 3D rule");
+        };
+        assert_eq!(separated.birth, 1 << 5 | 1 << 7);
+        assert_eq!(separated.survive, 1 << 4 | 1 << 5 | 1 << 26);
+        assert_eq!(separated.birth_range(), Some((5, 7)));
+        let Rule::Life3D(bays) = rule("5766") else {
+            panic!("should be a 3D rule");
+        };
+        assert_eq!(bays.survive, 0b1110_0000);
+        assert_eq!(bays.birth, 0b0100_0000);
+    }
+
+    #[test]
+    fn hensel_form_of_life_has_the_same_table() {
+        let hensel = rule("B3aceijknqry/S2aceikn3aceijknqry");
+        assert_eq!(hensel, Rule::CONWAY);
+        assert_eq!(hensel.lookup_table(), Rule::CONWAY.lookup_table());
+    }
+
+    #[test]
+    fn life_table_counts_neighbours() {
+        let table = Rule::CONWAY.lookup_table();
+        assert_eq!(table.len(), 256);
+        for (neighbourhood, &entry) in table.iter().enumerate() {
+            let count = neighbourhood.count_ones();
+            let (born, survives) = (entry & 1 != 0, entry & 2 != 0);
+            assert_eq!(born, count == 3, "birth with {neighbourhood:08b}");
+            assert_eq!(
+                survives,
+                count == 2 || count == 3,
+                "survival with {neighbourhood:08b}"
+            );
+        }
+    }
+
+    #[test]
+    fn triangular_counts_are_not_mistaken_for_prefixes() {
+        assert_eq!(rule("ab/3L").to_string(), "B3/SabL");
+        assert_eq!(rule("12/bcL").to_string(), "Bbc/S12L");
+        assert_eq!(rule("bc/b/3L").to_string(), "Bb/Sbc/C3L");
+        assert_eq!(rule("sAB/b3L").to_string(), "B3/SabL");
+        assert_eq!(rule("Bbc/S2L").to_string(), "Bbc/S2L");
+    }
+
+    #[test]
+    fn invalid_rulestrings_are_rejected() {
+        for s in [
+            "",
+            "B9/S23",
+            "B33/S23",
+            "B2x/S23",
+            "B0123478/S34678H",
+            "45",
+            "5455",
+            "W256",
+            "a/2H",
+            "B2/S3aH",
+            "Bd/S2L",
+            "B3/23",
+            "5466",
+            "B27/S4,5",
+            "B5/S4,4",
+            "B5/S4,5/C3",
+            "B5,x/S4",
+            "B5/B4,5",
+        ] {
+            assert!(s.parse::<Rule>().is_err(), "'{s}' should be rejected");
+        }
+    }
+}
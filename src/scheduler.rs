@@ -0,0 +1,115 @@
+// Decides how many generations to simulate each frame, so that the simulation runs at a set
+// number of generations per second regardless of how often the window is redrawn.
+
+use instant::Instant;
+
+pub const DEFAULT_GENERATIONS_PER_SECOND: f64 = 10.0;
+pub const MIN_GENERATIONS_PER_SECOND: f64 = 0.1;
+pub const MAX_GENERATIONS_PER_SECOND: f64 = 100_000.0;
+
+// If the GPU can't keep up, we drop generations rather than queueing up more and more work.
+// The window ticks the scheduler many times between frames, so this bounds the generations
+// pending for a frame, not just those due at each tick.
+const MAX_GENERATIONS_PER_FRAME: u32 = 2_000;
+
+pub(crate) struct Scheduler {
+    generations_per_second: f64,
+    accumulated: f64, // fractional generations carried over between ticks
+    last_tick: Instant,
+}
+
+impl Scheduler {
+    pub(crate) fn new(generations_per_second: f64) -> Self {
+        Self {
+            generations_per_second: generations_per_second
+                .clamp(MIN_GENERATIONS_PER_SECOND, MAX_GENERATIONS_PER_SECOND),
+            accumulated: 0.0,
+            last_tick: Instant::now(),
+        }
+    }
+
+    pub(crate) fn generations_per_second(&self) -> f64 {
+        self.generations_per_second
+    }
+
+    pub(crate) fn set_generations_per_second(&mut self, generations_per_second: f64) {
+        self.generations_per_second =
+            generations_per_second.clamp(MIN_GENERATIONS_PER_SECOND, MAX_GENERATIONS_PER_SECOND);
+    }
+
+    // Restart the timing, e.g. after unpausing, so that the time spent paused isn't simulated
+    pub(crate) fn reset(&mut self) {
+        self.accumulated = 0.0;
+        self.last_tick = Instant::now();
+    }
+
+    // How many generations are due since the last tick
+    pub(crate) fn tick(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_tick).as_secs_f64();
+        self.last_tick = now;
+        self.advance(elapsed)
+    }
+
+    // How many generations are due after some seconds have passed
+    fn advance(&mut self, elapsed: f64) -> u32 {
+        self.accumulated += elapsed * self.generations_per_second;
+        let generations = self
+            .accumulated
+            .floor()
+            .min(MAX_GENERATIONS_PER_FRAME as f64);
+        // Only the fraction of the next generation carries over, so generations dropped by the
+        // cap are gone for good
+        self.accumulated = self.accumulated.fract();
+
+        generations as u32
+    }
+}
+
+// The generations to simulate in the next frame, once more are due on top of those still
+// pending from earlier ticks
+pub(crate) fn pending_generations(pending: u32, due: u32) -> u32 {
+    pending.saturating_add(due).min(MAX_GENERATIONS_PER_FRAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fractional_generations_carry_over() {
+        let mut scheduler = Scheduler::new(10.0);
+        assert_eq!(scheduler.advance(0.05), 0);
+        assert_eq!(scheduler.advance(0.05), 1);
+        assert_eq!(scheduler.advance(0.25), 2);
+        assert_eq!(scheduler.advance(0.05), 1);
+    }
+
+    #[test]
+    fn slow_speeds_take_several_seconds_per_generation() {
+        let mut scheduler = Scheduler::new(MIN_GENERATIONS_PER_SECOND);
+        assert_eq!(scheduler.advance(5.0), 0);
+        assert_eq!(scheduler.advance(5.0), 1);
+    }
+
+    #[test]
+    fn long_ticks_are_capped() {
+        let mut scheduler = Scheduler::new(MAX_GENERATIONS_PER_SECOND);
+        assert_eq!(scheduler.advance(1.000_005), MAX_GENERATIONS_PER_FRAME);
+        // The dropped generations aren't carried over, but the half generation after them is
+        assert_eq!(scheduler.advance(0.0), 0);
+        assert_eq!(scheduler.advance(0.000_006), 1);
+        assert_eq!(scheduler.advance(0.0), 0);
+    }
+
+    #[test]
+    fn pending_generations_are_capped_across_ticks() {
+        let mut pending = 0;
+        for _ in 0..10 {
+            pending = pending_generations(pending, MAX_GENERATIONS_PER_FRAME / 2);
+        }
+        assert_eq!(pending, MAX_GENERATIONS_PER_FRAME);
+        assert_eq!(pending_generations(3, 4), 7);
+        assert_eq!(pending_generations(u32::MAX, 1), MAX_GENERATIONS_PER_FRAME);
+    }
+}
@@ -1,6 +1,82 @@
-@group(0) @binding(0) var<uniform> grid: vec2<f32>;
+// The grid's width, height and depth. Only 3D rules have more than one layer.
+@group(0) @binding(0) var<uniform> grid: vec3<f32>;
 @group(0) @binding(1) var<storage> cell_state_in: array<u32>;
-@group(0) @binding(2) var<storage, read_write> cell_state_out: array<u32>;
+@group(0) @binding(3) var<uniform> rule: Rule;
+@group(0) @binding(4) var<storage> cell_age_in: array<u32>;
+@group(0) @binding(8) var<storage> cell_value_in: array<f32>;
+@group(0) @binding(11) var<uniform> history: History;
+@group(1) @binding(0) var<uniform> view: View;
+@group(1) @binding(1) var<uniform> style: Style;
+@group(1) @binding(2) var<uniform> orbit: Orbit;
+
+// Only the kind of rule, the number of states and the lattice are needed: to colour the states
+// of Wireworld, the dying states of Generations rules and the values of Lenia, to draw the
+// history of one dimensional rules, and to shape the cells
+struct Rule {
+    kind: u32,
+    states: u32,
+    lattice: u32,
+};
+
+// The row holding the current generation of a one dimensional rule. Must match HistoryUniform
+// in simulation.rs.
+struct History {
+    row: u32,
+};
+
+// Must match RuleUniform in rule.rs
+const RULE_KIND_LIFE: u32 = 0u;
+const RULE_KIND_WIREWORLD: u32 = 1u;
+const RULE_KIND_LARGER_THAN_LIFE: u32 = 2u;
+const RULE_KIND_LENIA: u32 = 3u;
+const RULE_KIND_ELEMENTARY: u32 = 4u;
+const LATTICE_HEXAGONAL: u32 = 1u;
+const LATTICE_TRIANGULAR: u32 = 2u;
+
+// Camera transform from world space, where the grid spans -1..1, to clip space
+struct View {
+    scale: vec2<f32>,
+    offset: vec2<f32>,
+};
+
+// The orbit camera's transform for 3D grids, from world space, where the grid's longest side
+// spans -1..1, to clip space. Must match OrbitUniform in camera.rs.
+struct Orbit {
+    view_projection: mat4x4<f32>,
+};
+
+// How the cells are coloured
+struct Style {
+    colour_mode: u32,
+    // How many generations a dead cell fades out over, or 0 if trails are off
+    trail_length: u32,
+    // The palette: a base colour for live cells, the colours of the three lamps that light
+    // cells by position, the age gradient from newborn to old, and the colours of Wireworld's
+    // electron heads, electron tails and conductors
+    alive: vec4<f32>,
+    lamps: array<vec4<f32>, 3>,
+    age_gradient: array<vec4<f32>, 4>,
+    wireworld: array<vec4<f32>, 3>,
+    // The colour of the grid lines, which contrasts with the background
+    grid_line_colour: vec4<f32>,
+};
+
+// Must match ColourMode in style.rs
+const COLOUR_MODE_POSITION: u32 = 0u;
+const COLOUR_MODE_AGE: u32 = 1u;
+
+// Grid lines fade out as cells shrink to this many pixels across, and are hidden below it
+const GRID_LINE_MIN_CELL_PIXELS: f32 = 6.0;
+// Grid lines are this fraction of a cell thick, within the pixel limits below
+const GRID_LINE_WIDTH_FRACTION: f32 = 0.05;
+const GRID_LINE_MIN_PIXELS: f32 = 1.0;
+const GRID_LINE_MAX_PIXELS: f32 = 3.0;
+
+// Cells this many generations old or older are given the last colour of the age gradient
+const AGE_GRADIENT_GENERATIONS: f32 = 256.0;
+
+// Continuous values below this fade into the background, so low values don't end abruptly
+const VALUE_FADE: f32 = 0.2;
 
 // Vertex shader
 
@@ -12,6 +88,10 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) cell: vec2<f32>,
+    @location(1) @interpolate(flat) age: u32,
+    @location(2) @interpolate(flat) state: u32,
+    // Continuous rules only
+    @location(3) @interpolate(flat) value: f32,
 };
 
 @vertex
@@ -21,14 +101,41 @@ fn vs_main(
     var output: VertexOutput;
     
     let i = f32(input.instance);
-    let cell = vec2<f32>(i % grid.x, floor(i / grid.x));
-    let cell_offset = cell / grid * 2;
+    var cell = vec2<f32>(i % grid.x, floor(i / grid.x));
+    // One dimensional rules keep their history in a ring of rows. Time runs down the grid, so
+    // the current generation is drawn at the bottom with the older generations above it.
+    if (rule.kind == RULE_KIND_ELEMENTARY) {
+        let generations_ago = (history.row + u32(grid.y) - u32(cell.y)) % u32(grid.y);
+        cell.y = f32(generations_ago);
+    }
     output.cell = cell;
+    // Odd rows of hexagons are shifted right by half a slot so that the rows interlock
+    var slot = cell;
+    if (rule.lattice == LATTICE_HEXAGONAL) {
+        slot.x += f32(u32(cell.y) & 1u) * 0.5;
+    }
+    let cell_offset = slot / grid.xy * 2;
+    output.age = cell_age_in[input.instance];
+    output.state = cell_state_in[input.instance];
+    // Other rules only have a placeholder value buffer
+    if (rule.kind == RULE_KIND_LENIA) {
+        output.value = cell_value_in[input.instance];
+    }
 
-    let state = f32(cell_state_in[input.instance]);
+    // Dead cells collapse to a zero size quad, unless they died recently enough to leave a
+    // trail. A dead cell's age is how many generations ago it died.
+    let trail = output.state == 0u && output.age <= style.trail_length;
+    let visible = f32(output.state != 0u || trail);
+
+    // The vertices make an upward triangle on the triangular lattice, so cells pointing down
+    // flip it
+    var position = input.position;
+    if (rule.lattice == LATTICE_TRIANGULAR && ((u32(cell.x) + u32(cell.y)) & 1u) == 1u) {
+        position.y = -position.y;
+    }
 
-    let grid_pos = (input.position * state + 1) / grid - 1 + cell_offset;
-    output.clip_position = vec4<f32>(grid_pos, 0.0, 1.0);
+    let grid_pos = (position * visible + 1) / grid.xy - 1 + cell_offset;
+    output.clip_position = vec4<f32>(grid_pos * view.scale + view.offset, 0.0, 1.0);
 
     return output;
 }
@@ -37,19 +144,56 @@ fn vs_main(
 
  @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    if (rule.kind == RULE_KIND_WIREWORLD && input.state != 0u) {
+        // Wireworld's states each have their own colour
+        return vec4<f32>(style.wireworld[min(input.state, 3u) - 1u].rgb, 1.0);
+    }
+
+    if (rule.kind == RULE_KIND_LENIA && input.state != 0u) {
+        // Continuous values are coloured along the age gradient, from its end at low values
+        // to its start at 1
+        let fade = smoothstep(0.0, VALUE_FADE, input.value);
+        return vec4<f32>(gradient_colour(1.0 - input.value), fade);
+    }
+
+    if (input.state >= 2u) {
+        // Dying cells of Generations rules are coloured along the age gradient, from the first
+        // dying state to the last
+        let dying = f32(input.state - 1u) / f32(rule.states - 1u);
+        return vec4<f32>(gradient_colour(dying), 1.0);
+    }
+
+    let colour = cell_colour(input.cell, input.age);
+    if (input.state == 0u) {
+        // Trails fade out over trail_length generations
+        let fade = 1.0 - f32(input.age) / f32(style.trail_length + 1u);
+        return vec4<f32>(colour, fade);
+    }
+    return vec4<f32>(colour, 1.0);
+}
+
+// The colour of a live cell at a position in the grid, which has been alive for age generations
+fn cell_colour(cell: vec2<f32>, age: u32) -> vec3<f32> {
+    if (style.colour_mode == COLOUR_MODE_AGE) {
+        return age_colour(age);
+    }
+
     // Cell values range from 0 to grid-1.
     // Colors are in the range 0 to 1. So we divide cell position by grid.
-    let cell_clipped = input.cell / grid;
+    let cell_clipped = cell / grid.xy;
 
-    let red_lamp_location   = vec2<f32>(0.0, 1.0/6.0);
-    let green_lamp_location = vec2<f32>(0.5, 1.0);
-    let blue_lamp_location  = vec2<f32>(1.0, 1.0/6.0);
+    let left_lamp_location   = vec2<f32>(0.0, 1.0/6.0);
+    let middle_lamp_location = vec2<f32>(0.5, 1.0);
+    let right_lamp_location  = vec2<f32>(1.0, 1.0/6.0);
 
-    let red_val = colour_lamp_brightness(cell_clipped, red_lamp_location);
-    let green_val = colour_lamp_brightness(cell_clipped, green_lamp_location);
-    let blue_val = colour_lamp_brightness(cell_clipped, blue_lamp_location);
+    let left_val = colour_lamp_brightness(cell_clipped, left_lamp_location);
+    let middle_val = colour_lamp_brightness(cell_clipped, middle_lamp_location);
+    let right_val = colour_lamp_brightness(cell_clipped, right_lamp_location);
 
-    return vec4<f32>(red_val, green_val, blue_val, 1.0);
+    return style.alive.rgb +
+           style.lamps[0].rgb * left_val +
+           style.lamps[1].rgb * middle_val +
+           style.lamps[2].rgb * right_val;
 }
 
 fn colour_lamp_brightness(cell_clipped: vec2<f32>, lamp_location: vec2<f32>) -> f32 {
@@ -58,41 +202,167 @@ fn colour_lamp_brightness(cell_clipped: vec2<f32>, lamp_location: vec2<f32>) ->
     return brightness;
 }
 
-// Compute shader
+// Cells fade through the palette's age gradient as they age, from newborn to old.
+// Age is on a log scale so that young cells, which change the most, are spread out the most.
+fn age_colour(age: u32) -> vec3<f32> {
+    return gradient_colour(log2(f32(max(age, 1u))) / log2(AGE_GRADIENT_GENERATIONS));
+}
 
-fn cell_index(cell: vec2<u32>) -> u32 {
-    return (cell.y % u32(grid.y)) * u32(grid.x) +
-           (cell.x % u32(grid.x));
+// The palette's age gradient, at a position from 0 (newborn) to 1 (old)
+fn gradient_colour(position: f32) -> vec3<f32> {
+    let t = clamp(position, 0.0, 1.0) * 3.0;
+
+    let newborn = style.age_gradient[0].rgb;
+    let young = style.age_gradient[1].rgb;
+    let mature = style.age_gradient[2].rgb;
+    let old = style.age_gradient[3].rgb;
+
+    if (t < 1.0) {
+        return mix(newborn, young, t);
+    } else if (t < 2.0) {
+        return mix(young, mature, t - 1.0);
+    }
+    return mix(mature, old, t - 2.0);
 }
 
-fn cell_active(x: u32, y: u32) -> u32 {
-    return cell_state_in[cell_index(vec2(x, y))];
+// Grid lines
+
+struct GridLinesOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Position in cells, so cell edges fall on whole numbers
+    @location(0) cell: vec2<f32>,
+};
+
+// Two triangles covering the whole grid, drawn over the cells
+@vertex
+fn vs_grid_lines(@builtin(vertex_index) index: u32) -> GridLinesOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let world = corners[index];
+
+    var output: GridLinesOutput;
+    output.clip_position = vec4<f32>(world * view.scale + view.offset, 0.0, 1.0);
+    output.cell = (world + 1.0) / 2.0 * grid.xy;
+    return output;
 }
 
-@compute @workgroup_size(8, 8)
-fn cs_main(@builtin(global_invocation_id) cell: vec3<u32>) {
-    // Determine how many active neighbors this cell has.
-    let active_neighbours = cell_active(cell.x+1, cell.y+1) +
-                          cell_active(cell.x+1, cell.y) +
-                          cell_active(cell.x+1, cell.y-1) +
-                          cell_active(cell.x, cell.y-1) +
-                          cell_active(cell.x-1, cell.y-1) +
-                          cell_active(cell.x-1, cell.y) +
-                          cell_active(cell.x-1, cell.y+1) +
-                          cell_active(cell.x, cell.y+1);
-
-    let i = cell_index(cell.xy);
-
-    // Conway's game of life rules:
-    switch active_neighbours {
-        case 2u: { // Active cells with 2 neighbors stay active.
-            cell_state_out[i] = cell_state_in[i];
-        }
-        case 3u: { // Cells with 3 neighbors become or stay active.
-            cell_state_out[i] = 1u;
-        }
-        default: { // Cells with < 2 or > 3 neighbors become inactive.
-            cell_state_out[i] = 0u;
-        }
+@fragment
+fn fs_grid_lines(input: GridLinesOutput) -> @location(0) vec4<f32> {
+    // How many cells each pixel covers, which tells us how large the cells are on screen
+    let cells_per_pixel = fwidth(input.cell);
+    let cell_pixels = 1.0 / max(cells_per_pixel.x, cells_per_pixel.y);
+
+    // The lines get thicker as we zoom in, and fade out before the cells get too small
+    let line_width = clamp(
+        cell_pixels * GRID_LINE_WIDTH_FRACTION,
+        GRID_LINE_MIN_PIXELS,
+        GRID_LINE_MAX_PIXELS,
+    );
+    let fade = smoothstep(GRID_LINE_MIN_CELL_PIXELS, 2.0 * GRID_LINE_MIN_CELL_PIXELS, cell_pixels);
+
+    // Distance in pixels to the nearest cell edge, with the line's edges antialiased
+    let edge_distance = abs(fract(input.cell + 0.5) - 0.5) / cells_per_pixel;
+    let distance = min(edge_distance.x, edge_distance.y);
+    let coverage = 1.0 - smoothstep(line_width / 2.0 - 0.5, line_width / 2.0 + 0.5, distance);
+
+    let alpha = style.grid_line_colour.a * coverage * fade;
+    if (alpha <= 0.0) {
+        discard;
     }
-}
\ No newline at end of file
+    return vec4<f32>(style.grid_line_colour.rgb, alpha);
+}
+
+// 3D grids
+
+// Cubes are drawn at this fraction of their slot, leaving a gap between neighbours
+const CUBE_INSET: f32 = 0.8;
+// The direction the light comes from, above and in front of the grid, and how bright the faces
+// turned away from it are
+const LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.36, 0.8, 0.48);
+const AMBIENT_LIGHT: f32 = 0.45;
+
+struct CubeOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // The cell's column and row, which it's coloured by as in a 2D grid
+    @location(0) cell: vec2<f32>,
+    @location(1) @interpolate(flat) age: u32,
+    // How brightly the light falls on the face
+    @location(2) @interpolate(flat) brightness: f32,
+};
+
+// Each instance is a cell, drawn as 6 faces of 2 triangles. Dead cells collapse to a point.
+@vertex
+fn vs_cube(
+    @builtin(vertex_index) index: u32,
+    @builtin(instance_index) instance: u32,
+) -> CubeOutput {
+    let size = vec3<u32>(grid);
+    let cell = vec3<u32>(
+        instance % size.x,
+        instance / size.x % size.y,
+        instance / (size.x * size.y),
+    );
+
+    // A face is spanned by two axes whose cross product is its normal, so its triangles wind
+    // anticlockwise when seen from outside the cube
+    var normals = array<vec3<f32>, 6>(
+        vec3<f32>(1.0, 0.0, 0.0),
+        vec3<f32>(-1.0, 0.0, 0.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(0.0, -1.0, 0.0),
+        vec3<f32>(0.0, 0.0, 1.0),
+        vec3<f32>(0.0, 0.0, -1.0),
+    );
+    var first_axes = array<vec3<f32>, 6>(
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(0.0, 0.0, 1.0),
+        vec3<f32>(0.0, 0.0, 1.0),
+        vec3<f32>(1.0, 0.0, 0.0),
+        vec3<f32>(1.0, 0.0, 0.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+    );
+    var second_axes = array<vec3<f32>, 6>(
+        vec3<f32>(0.0, 0.0, 1.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(1.0, 0.0, 0.0),
+        vec3<f32>(0.0, 0.0, 1.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(1.0, 0.0, 0.0),
+    );
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let face = index / 6u;
+    let corner = corners[index % 6u];
+    let normal = normals[face];
+    let position = normal + first_axes[face] * corner.x + second_axes[face] * corner.y;
+
+    // The grid is centred on the origin, with its longest side spanning -1..1
+    let slot = 2.0 / max(grid.x, max(grid.y, grid.z));
+    let centre = (vec3<f32>(cell) + 0.5 - grid / 2.0) * slot;
+    let visible = f32(cell_state_in[instance] != 0u);
+    let world = centre + position * slot / 2.0 * CUBE_INSET * visible;
+
+    var output: CubeOutput;
+    output.clip_position = orbit.view_projection * vec4<f32>(world, 1.0);
+    output.cell = vec2<f32>(cell.xy);
+    output.age = cell_age_in[instance];
+    output.brightness = mix(AMBIENT_LIGHT, 1.0, max(dot(normal, LIGHT_DIRECTION), 0.0));
+    return output;
+}
+
+@fragment
+fn fs_cube(input: CubeOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(cell_colour(input.cell, input.age) * input.brightness, 1.0);
+}
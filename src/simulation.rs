@@ -0,0 +1,1373 @@
+// The GPU simulation: the compute pipeline and the ping pong pair of cell state buffers.
+// This doesn't depend on a window or surface, so it can be run headless (e.g. from tests or
+// batch jobs) as well as being composed by the renderer in lib.rs.
+
+use crate::config::default_volume;
+use crate::population::{PopulationCounter, PopulationSample};
+use crate::rle::Pattern;
+use crate::rule::{Rule, RuleUniform, MAX_LENIA_RADIUS, RULE_TABLE_SIZE};
+use crate::topology::{Topology, TopologyUniform};
+use std::borrow::Cow;
+use std::fmt;
+#[allow(unused_imports)]
+use tracing::{error, info, warn};
+use wgpu::util::DeviceExt;
+
+// Must match the @workgroup_size of the entry points in compute.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+// One dimensional rules' entry points run over a row rather than the grid
+const ROW_WORKGROUP_SIZE: u32 = 64;
+// 3D rules' entry points run over a cube of cells in each dimension
+const VOLUME_WORKGROUP_SIZE: u32 = 4;
+
+// The grid's width, height and depth. Only 3D grids have more than one layer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GridSizeUniform {
+    grid: [f32; 3],
+    _padding: f32,
+}
+
+impl GridSizeUniform {
+    fn new(width: u32, height: u32, depth: u32) -> Self {
+        Self {
+            grid: [width as f32, height as f32, depth as f32],
+            _padding: 0.0,
+        }
+    }
+}
+
+struct CellState {
+    state_a: Vec<u32>,
+    state_b: Vec<u32>,
+    // The values of state_a's cells, for continuous rules
+    values: Vec<f32>,
+}
+
+enum InitialCellState {
+    // Random states suited to the rule
+    Random(Rule),
+    Empty,
+}
+
+impl CellState {
+    fn new(init: InitialCellState, width: u32, height: u32, depth: u32) -> Self {
+        let num_cells = (width * height * depth) as usize;
+        let mut grid_a = vec![0; num_cells];
+        let mut grid_b = vec![0; num_cells];
+        let row_width = width as usize;
+
+        // Continuous rules start from random values, and their states follow from them
+        let mut values = None;
+        match init {
+            InitialCellState::Random(rule) if rule.is_continuous() => {
+                let mut rng = rand::thread_rng();
+                let random_values: Vec<f32> = (0..num_cells)
+                    .map(|_| rule.random_value(&mut rng))
+                    .collect();
+                for (cell, value) in grid_a.iter_mut().zip(&random_values) {
+                    *cell = (*value > 0.0) as u32;
+                }
+                values = Some(random_values);
+            }
+            // One dimensional rules only start with the first row, which is the current
+            // generation. The rest of the grid is its history, which both buffers hold.
+            InitialCellState::Random(rule) if rule.is_one_dimensional() => {
+                let mut rng = rand::thread_rng();
+
+                for cell in grid_a[..row_width].iter_mut() {
+                    *cell = rule.random_state(&mut rng);
+                }
+                grid_b.copy_from_slice(&grid_a);
+            }
+            InitialCellState::Random(rule) => {
+                let mut rng = rand::thread_rng();
+
+                for cell in grid_a.iter_mut() {
+                    *cell = rule.random_state(&mut rng);
+                }
+            }
+            InitialCellState::Empty => {}
+        }
+
+        Self {
+            values: values.unwrap_or_else(|| initial_values(&grid_a)),
+            state_a: grid_a,
+            state_b: grid_b,
+        }
+    }
+
+    // Place a pattern in the centre of an empty grid
+    fn from_pattern(pattern: &Pattern, width: u32, height: u32, depth: u32, rule: Rule) -> Self {
+        let state_a = pattern.starting_cells(rule, width, height, depth);
+        let state_b = if rule.is_one_dimensional() {
+            state_a.clone()
+        } else {
+            vec![0; state_a.len()]
+        };
+        Self {
+            values: initial_values(&state_a),
+            state_b,
+            state_a,
+        }
+    }
+}
+
+// The age given to dead cells that haven't died recently, e.g. when the grid is reset, so that
+// they don't leave trails. The compute shader's age count saturates at this value.
+const LONG_DEAD_AGE: u32 = u32::MAX;
+
+// The ages of cells whose state was just set: live cells are newborn, and dead cells are
+// long dead
+fn initial_ages(cells: &[u32]) -> Vec<u32> {
+    cells
+        .iter()
+        .map(|&cell| if cell == 0 { LONG_DEAD_AGE } else { 0 })
+        .collect()
+}
+
+// The values of cells whose state was just set, for continuous rules: live cells have the
+// largest value, and any other cells none
+fn initial_values(cells: &[u32]) -> Vec<f32> {
+    cells
+        .iter()
+        .map(|&cell| (cell == 1) as u32 as f32)
+        .collect()
+}
+
+// The rule's uniform, the lookup table of Life-like rules, and the kernel of Lenia rules
+struct RuleBuffers {
+    uniform: wgpu::Buffer,
+    table: wgpu::Buffer,
+    kernel: wgpu::Buffer,
+}
+
+impl RuleBuffers {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, rule: Rule) -> Self {
+        let create_buffer = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: usage | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        // The table and kernel buffers are large enough for any rule, so they're never
+        // reallocated
+        let kernel_diameter = (2 * MAX_LENIA_RADIUS + 1) as u64;
+        let buffers = Self {
+            uniform: create_buffer(
+                "Rule Uniforms",
+                std::mem::size_of::<RuleUniform>() as u64,
+                wgpu::BufferUsages::UNIFORM,
+            ),
+            table: create_buffer(
+                "Rule Table",
+                std::mem::size_of::<[u32; RULE_TABLE_SIZE]>() as u64,
+                wgpu::BufferUsages::STORAGE,
+            ),
+            kernel: create_buffer(
+                "Lenia Kernel",
+                kernel_diameter * kernel_diameter * std::mem::size_of::<f32>() as u64,
+                wgpu::BufferUsages::STORAGE,
+            ),
+        };
+        buffers.write(queue, rule);
+        buffers
+    }
+
+    fn write(&self, queue: &wgpu::Queue, rule: Rule) {
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::cast_slice(&[RuleUniform::from(rule)]),
+        );
+        let table = rule.lookup_table();
+        if !table.is_empty() {
+            queue.write_buffer(&self.table, 0, bytemuck::cast_slice(&table));
+        }
+        let weights = rule.kernel_weights();
+        if !weights.is_empty() {
+            queue.write_buffer(&self.kernel, 0, bytemuck::cast_slice(&weights));
+        }
+    }
+}
+
+// Where the current generation of a one dimensional rule is in the ring of rows its history is
+// drawn in. Must match History in compute.wgsl and shader.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct HistoryUniform {
+    row: u32,
+    _padding: [u32; 3],
+}
+
+// The history uniform, and a buffer holding the number of each row of the grid. Many
+// generations are simulated in one submission, so rather than the uniform being written from
+// the CPU, each step copies the row of its generation into it from the row numbers.
+// These are reallocated whenever the grid dimensions change.
+struct HistoryBuffers {
+    uniform: wgpu::Buffer,
+    row_numbers: wgpu::Buffer,
+    height: u32,
+}
+
+impl HistoryBuffers {
+    fn new(device: &wgpu::Device, height: u32) -> Self {
+        let row_numbers: Vec<u32> = (0..height).collect();
+        Self {
+            uniform: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("History Uniforms"),
+                contents: bytemuck::cast_slice(&[HistoryUniform {
+                    row: 0,
+                    _padding: [0; 3],
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }),
+            row_numbers: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("History Row Numbers"),
+                contents: bytemuck::cast_slice(&row_numbers),
+                usage: wgpu::BufferUsages::COPY_SRC,
+            }),
+            height,
+        }
+    }
+
+    // The generations take turns to use the rows, so each replaces the oldest
+    fn row(&self, generation: u32) -> u32 {
+        generation % self.height
+    }
+
+    fn write(&self, queue: &wgpu::Queue, generation: u32) {
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::cast_slice(&[self.row(generation)]),
+        );
+    }
+
+    fn encode_write(&self, encoder: &mut wgpu::CommandEncoder, generation: u32) {
+        let size = std::mem::size_of::<u32>() as u64;
+        let offset = self.row(generation) as u64 * size;
+        encoder.copy_buffer_to_buffer(&self.row_numbers, offset, &self.uniform, 0, size);
+    }
+}
+
+// We use two bind groups to enable the ping pong buffer pattern
+struct BindGroups {
+    group_a: wgpu::BindGroup,
+    group_b: wgpu::BindGroup,
+}
+
+impl BindGroups {
+    fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        grid_size_uniform_buffer: &wgpu::Buffer,
+        rule_buffers: &RuleBuffers,
+        topology_uniform_buffer: &wgpu::Buffer,
+        history_buffers: &HistoryBuffers,
+        cell_state_buffers: &CellStateBuffers,
+    ) -> Self {
+        Self {
+            group_a: device.create_bind_group(&wgpu::BindGroupDescriptor {
+                // bind group a:
+                // - cell state input: state a
+                // - cell state output: state b
+                // - cell age input: age a
+                // - cell age output: age b
+                // - cell value input: value a
+                // - cell value output: value b
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: grid_size_uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: cell_state_buffers.state_a.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: cell_state_buffers.state_b.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: rule_buffers.uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: cell_state_buffers.age_a.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: cell_state_buffers.age_b.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: topology_uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: rule_buffers.table.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: cell_state_buffers.value_a.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 9,
+                        resource: cell_state_buffers.value_b.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 10,
+                        resource: rule_buffers.kernel.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 11,
+                        resource: history_buffers.uniform.as_entire_binding(),
+                    },
+                ],
+                label: Some("Cell Bind Group A"),
+            }),
+            group_b: device.create_bind_group(&wgpu::BindGroupDescriptor {
+                // bind group b:
+                // - cell state input: state b
+                // - cell state output: state a
+                // - cell age input: age b
+                // - cell age output: age a
+                // - cell value input: value b
+                // - cell value output: value a
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: grid_size_uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: cell_state_buffers.state_b.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: cell_state_buffers.state_a.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: rule_buffers.uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: cell_state_buffers.age_b.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: cell_state_buffers.age_a.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: topology_uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: rule_buffers.table.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: cell_state_buffers.value_b.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 9,
+                        resource: cell_state_buffers.value_a.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 10,
+                        resource: rule_buffers.kernel.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 11,
+                        resource: history_buffers.uniform.as_entire_binding(),
+                    },
+                ],
+                label: Some("Cell Bind Group B"),
+            }),
+        }
+    }
+}
+
+// A compute pipeline for each kind of rule, running its entry point in compute.wgsl.
+// They all share the simulation's bind group layout.
+struct ComputePipelines {
+    life: wgpu::ComputePipeline,
+    wireworld: wgpu::ComputePipeline,
+    larger_than_life: wgpu::ComputePipeline,
+    lenia: wgpu::ComputePipeline,
+    elementary: wgpu::ComputePipeline,
+    life_3d: wgpu::ComputePipeline,
+}
+
+impl ComputePipelines {
+    fn new(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout) -> Self {
+        // Load the compute shader from disk
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Compute Shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("compute.wgsl"))),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Compute Pipeline Layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        Self {
+            life: create_pipeline("Compute Pipeline", "cs_main"),
+            wireworld: create_pipeline("Wireworld Compute Pipeline", "cs_wireworld"),
+            larger_than_life: create_pipeline(
+                "Larger than Life Compute Pipeline",
+                "cs_larger_than_life",
+            ),
+            lenia: create_pipeline("Lenia Compute Pipeline", "cs_lenia"),
+            elementary: create_pipeline("Elementary Compute Pipeline", "cs_elementary"),
+            life_3d: create_pipeline("3D Life Compute Pipeline", "cs_life_3d"),
+        }
+    }
+
+    fn for_rule(&self, rule: Rule) -> &wgpu::ComputePipeline {
+        match rule {
+            Rule::Life(_) => &self.life,
+            Rule::Wireworld => &self.wireworld,
+            Rule::LargerThanLife(_) => &self.larger_than_life,
+            Rule::Lenia(_) => &self.lenia,
+            Rule::Elementary(_) => &self.elementary,
+            Rule::Life3D(_) => &self.life_3d,
+        }
+    }
+}
+
+// The ping pong pairs of cell state and cell age storage buffers.
+// A cell's age is how many generations it has been in its current state: how long a live
+// cell has been alive, or how long ago a dead cell died.
+// Continuous rules also have a ping pong pair of cell value buffers. Other rules don't use
+// them, so they hold a single placeholder value.
+// These are reallocated whenever the grid dimensions change.
+struct CellStateBuffers {
+    state_a: wgpu::Buffer,
+    state_b: wgpu::Buffer,
+    age_a: wgpu::Buffer,
+    age_b: wgpu::Buffer,
+    value_a: wgpu::Buffer,
+    value_b: wgpu::Buffer,
+}
+
+impl CellStateBuffers {
+    fn new(device: &wgpu::Device, cell_state: &CellState, rule: Rule) -> Self {
+        let values = if rule.is_continuous() {
+            cell_state.values.as_slice()
+        } else {
+            &[0.0]
+        };
+        let create_value_buffer = |label| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(values),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
+            })
+        };
+
+        Self {
+            state_a: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Cell State Storage Buffer A"),
+                contents: bytemuck::cast_slice(&cell_state.state_a),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
+            }),
+            state_b: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Cell State Storage Buffer B"),
+                contents: bytemuck::cast_slice(&cell_state.state_b),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
+            }),
+            age_a: Self::create_age_buffer(
+                device,
+                "Cell Age Storage Buffer A",
+                &cell_state.state_a,
+            ),
+            age_b: Self::create_age_buffer(
+                device,
+                "Cell Age Storage Buffer B",
+                &cell_state.state_b,
+            ),
+            value_a: create_value_buffer("Cell Value Storage Buffer A"),
+            value_b: create_value_buffer("Cell Value Storage Buffer B"),
+        }
+    }
+
+    fn create_age_buffer(device: &wgpu::Device, label: &str, cells: &[u32]) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&initial_ages(cells)),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum SimulationError {
+    // No adapter (GPU or software fallback) is available
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimulationError::NoAdapter => write!(f, "failed to find an appropriate adapter"),
+            SimulationError::RequestDevice(e) => write!(f, "failed to create device: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+// Find a GPU adapter, optionally one which can render to a surface, or else a software
+// fallback adapter if the platform has one
+pub(crate) async fn request_adapter(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface<'_>>,
+) -> Result<wgpu::Adapter, SimulationError> {
+    for force_fallback_adapter in [false, true] {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                force_fallback_adapter,
+                compatible_surface,
+            })
+            .await;
+        if let Some(adapter) = adapter {
+            return Ok(adapter);
+        }
+    }
+    Err(SimulationError::NoAdapter)
+}
+
+// The device limits we request. WebGL doesn't support all of wgpu's features, so if
+// we're building for the web we'll have to disable some.
+pub(crate) fn required_limits() -> wgpu::Limits {
+    if cfg!(target_arch = "wasm32") {
+        wgpu::Limits::downlevel_webgl2_defaults()
+    } else {
+        wgpu::Limits::default()
+    }
+}
+
+pub struct Simulation {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    width: u32,
+    height: u32,
+    depth: u32, // the number of layers, which is 1 unless the grid is 3D
+    rule: Rule,
+    topology: Topology,
+    generation: u32, // how many simulation steps have been run
+    grid_size_uniform_buffer: wgpu::Buffer,
+    rule_buffers: RuleBuffers,
+    topology_uniform_buffer: wgpu::Buffer,
+    history_buffers: HistoryBuffers,
+    cell_state_buffers: CellStateBuffers,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_groups: BindGroups,
+    compute_pipelines: ComputePipelines,
+    population_counter: PopulationCounter,
+}
+
+impl Simulation {
+    // Create a headless simulation with an empty grid, on a device of its own
+    pub async fn new(width: u32, height: u32, rule: Rule) -> Result<Self, SimulationError> {
+        let instance = wgpu::Instance::default();
+        let adapter = request_adapter(&instance, None).await?;
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    required_features: wgpu::Features::empty(),
+                    required_limits: required_limits(),
+                    label: None,
+                },
+                None, // Trace path
+            )
+            .await
+            .map_err(SimulationError::RequestDevice)?;
+
+        Ok(Self::from_device(device, queue, width, height, rule))
+    }
+
+    // Create a simulation with an empty grid on an existing device, e.g. one that can also
+    // render to a window surface
+    pub fn from_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        width: u32,
+        height: u32,
+        rule: Rule,
+    ) -> Self {
+        // Create grid size uniform buffer
+        let grid_size_uniform_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Grid Uniforms"),
+                contents: bytemuck::cast_slice(&[GridSizeUniform::new(width, height, 1)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        // Create the rule's buffers
+        let rule_buffers = RuleBuffers::new(&device, &queue, rule);
+
+        // Create topology uniform buffer
+        let topology = Topology::default();
+        let topology_uniform_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Topology Uniforms"),
+                contents: bytemuck::cast_slice(&[TopologyUniform::from(topology)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        // Create the history buffers, used by one dimensional rules
+        let history_buffers = HistoryBuffers::new(&device, height);
+
+        // Create cell state storage buffers
+        let cell_state = CellState::new(InitialCellState::Empty, width, height, 1);
+        let cell_state_buffers = CellStateBuffers::new(&device, &cell_state, rule);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::from_iter(
+                        wgpu::ShaderStages::VERTEX
+                            | wgpu::ShaderStages::FRAGMENT
+                            | wgpu::ShaderStages::COMPUTE,
+                    ),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // cell state input buffer (read only)
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::from_iter(
+                        wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                    ),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // cell state output buffer (read-write)
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::from_iter(wgpu::ShaderStages::COMPUTE),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // rule parameters, and the number of states for colouring dying cells
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::from_iter(
+                        wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                    ),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // cell age input buffer (read only)
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::from_iter(
+                        wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                    ),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // cell age output buffer (read-write)
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::from_iter(wgpu::ShaderStages::COMPUTE),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // grid topology
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::from_iter(wgpu::ShaderStages::COMPUTE),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // the rule's transitions for each arrangement of neighbours (read only)
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::from_iter(wgpu::ShaderStages::COMPUTE),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // cell value input buffer (read only)
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::from_iter(
+                        wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                    ),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // cell value output buffer (read-write)
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::from_iter(wgpu::ShaderStages::COMPUTE),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // the Lenia kernel's weights (read only)
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::from_iter(wgpu::ShaderStages::COMPUTE),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    // the row holding the current generation of a one dimensional rule
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::from_iter(
+                        wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                    ),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("bind_group_layout"),
+        });
+
+        let bind_groups = BindGroups::new(
+            &device,
+            &bind_group_layout,
+            &grid_size_uniform_buffer,
+            &rule_buffers,
+            &topology_uniform_buffer,
+            &history_buffers,
+            &cell_state_buffers,
+        );
+
+        // Create the compute pipelines
+        let compute_pipelines = ComputePipelines::new(&device, &bind_group_layout);
+
+        let population_counter = PopulationCounter::new(
+            &device,
+            [&cell_state_buffers.state_a, &cell_state_buffers.state_b],
+            [&cell_state_buffers.value_a, &cell_state_buffers.value_b],
+            &history_buffers.uniform,
+            width,
+            width * height,
+            rule,
+        );
+
+        let mut simulation = Self {
+            device,
+            queue,
+            width,
+            height,
+            depth: 1,
+            rule,
+            topology,
+            generation: 0,
+            grid_size_uniform_buffer,
+            rule_buffers,
+            topology_uniform_buffer,
+            history_buffers,
+            cell_state_buffers,
+            bind_group_layout,
+            bind_groups,
+            compute_pipelines,
+            population_counter,
+        };
+        simulation.count_population();
+        simulation
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    // The number of cells in every layer of the grid
+    pub fn num_cells(&self) -> u32 {
+        self.width * self.height * self.depth
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    // How many generations have been simulated since the grid was last reset
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    // Switch to a different rule. The cell state is kept so the new rule continues from the
+    // current generation, unless the switch is between a continuous and a discrete rule, which
+    // store cells differently, or between a 2D and a 3D rule. The grid is cleared then, 2D
+    // rules only keep one layer, and 3D rules turn a 2D grid into its default volume.
+    pub fn set_rule(&mut self, rule: Rule) {
+        info!("Switching rule to {}", rule);
+        let continuity_changed = rule.is_continuous() != self.rule.is_continuous();
+        let dimensions_changed = rule.is_three_dimensional() != self.rule.is_three_dimensional();
+        let history_started = rule.is_one_dimensional() && !self.rule.is_one_dimensional();
+        self.rule = rule;
+        self.rule_buffers.write(&self.queue, rule);
+        self.population_counter.set_rule(&self.queue, rule);
+        self.history_buffers.write(&self.queue, self.generation);
+        if continuity_changed || dimensions_changed {
+            let (width, height, depth) = if !rule.is_three_dimensional() {
+                (self.width, self.height, 1)
+            } else if dimensions_changed {
+                default_volume(self.width, self.height)
+            } else {
+                (self.width, self.height, self.depth)
+            };
+            self.resize_3d(width, height, depth);
+        } else if history_started {
+            self.copy_current_generation();
+        }
+    }
+
+    // Change how the edges of the grid join up. The cell state is kept.
+    pub fn set_topology(&mut self, topology: Topology) {
+        info!("Switching topology to {}", topology);
+        self.topology = topology;
+        self.queue.write_buffer(
+            &self.topology_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[TopologyUniform::from(topology)]),
+        );
+    }
+
+    // Advance the simulation by n generations
+    pub fn step(&mut self, n: u32) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Compute Encoder"),
+            });
+        self.encode_steps(&mut encoder, n);
+        self.queue.submit(Some(encoder.finish()));
+        self.submitted();
+    }
+
+    // Record n compute passes into an encoder, so the caller can submit them together with
+    // other work such as a render pass. The caller must call submitted once the encoder has
+    // been submitted.
+    pub(crate) fn encode_steps(&mut self, encoder: &mut wgpu::CommandEncoder, n: u32) {
+        if n == 0 {
+            return;
+        }
+
+        self.population_counter
+            .begin(&self.device, self.generation + 1, n);
+        for _ in 0..n {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(self.compute_pipelines.for_rule(self.rule));
+            compute_pass.set_bind_group(0, self.bind_group(), &[]);
+
+            if self.rule.is_one_dimensional() {
+                compute_pass.dispatch_workgroups(self.width.div_ceil(ROW_WORKGROUP_SIZE), 1, 1);
+            } else if self.rule.is_three_dimensional() {
+                compute_pass.dispatch_workgroups(
+                    self.width.div_ceil(VOLUME_WORKGROUP_SIZE),
+                    self.height.div_ceil(VOLUME_WORKGROUP_SIZE),
+                    self.depth.div_ceil(VOLUME_WORKGROUP_SIZE),
+                );
+            } else {
+                let workgroup_count_x = self.width.div_ceil(WORKGROUP_SIZE);
+                let workgroup_count_y = self.height.div_ceil(WORKGROUP_SIZE);
+                compute_pass.dispatch_workgroups(workgroup_count_x, workgroup_count_y, 1);
+            }
+            drop(compute_pass);
+
+            // After each step the output buffer becomes the input buffer of the next step
+            self.generation += 1;
+            if self.rule.is_one_dimensional() {
+                self.history_buffers.encode_write(encoder, self.generation);
+                self.encode_history_row_copy(encoder);
+            }
+            self.population_counter
+                .encode_count(encoder, self.generation);
+        }
+        self.population_counter.end();
+    }
+
+    // Start reading back the population counts recorded by encode_steps, and collect any
+    // that have arrived since the last call
+    pub(crate) fn submitted(&mut self) {
+        self.population_counter.submitted();
+        self.poll_population();
+    }
+
+    // Count the population of the current generation, e.g. after its cells were overwritten
+    fn count_population(&mut self) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Population Encoder"),
+            });
+        self.population_counter
+            .begin(&self.device, self.generation, 1);
+        self.population_counter
+            .encode_count(&mut encoder, self.generation);
+        self.population_counter.end();
+        self.queue.submit(Some(encoder.finish()));
+        self.submitted();
+    }
+
+    // Collect the population counts that have been read back from the GPU, without blocking.
+    // Counts arrive asynchronously, so they lag a frame or so behind the simulation.
+    pub fn poll_population(&mut self) {
+        self.device.poll(wgpu::Maintain::Poll);
+        self.population_counter.poll();
+    }
+
+    // Block until the population of every simulated generation has been read back.
+    // This isn't available on the web, where the browser maps buffers in its own time.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn wait_for_population(&mut self) {
+        self.device.poll(wgpu::Maintain::Wait);
+        self.population_counter.poll();
+    }
+
+    // The most recent population count that has been read back, if any
+    pub fn population(&self) -> Option<PopulationSample> {
+        self.population_counter.latest()
+    }
+
+    // The population of recent generations, oldest first.
+    // Only the last POPULATION_HISTORY_LENGTH generations are kept.
+    pub fn population_history(
+        &self,
+    ) -> impl ExactSizeIterator<Item = PopulationSample> + Clone + '_ {
+        self.population_counter.history().iter().copied()
+    }
+
+    pub(crate) fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    // The bind group whose input is the current generation
+    pub(crate) fn bind_group(&self) -> &wgpu::BindGroup {
+        if self.generation.is_multiple_of(2) {
+            &self.bind_groups.group_a
+        } else {
+            &self.bind_groups.group_b
+        }
+    }
+
+    // The buffer holding a generation
+    fn cell_state_buffer(&self, generation: u32) -> &wgpu::Buffer {
+        if generation.is_multiple_of(2) {
+            &self.cell_state_buffers.state_a
+        } else {
+            &self.cell_state_buffers.state_b
+        }
+    }
+
+    // The buffer holding the ages of a generation's cells
+    fn cell_age_buffer(&self, generation: u32) -> &wgpu::Buffer {
+        if generation.is_multiple_of(2) {
+            &self.cell_state_buffers.age_a
+        } else {
+            &self.cell_state_buffers.age_b
+        }
+    }
+
+    // The buffer holding the current generation
+    fn current_cell_state_buffer(&self) -> &wgpu::Buffer {
+        self.cell_state_buffer(self.generation)
+    }
+
+    // The generations whose buffers cells written into the current generation go into. Each
+    // step of a one dimensional rule only writes its new row, so both buffers hold the history.
+    fn written_generations(&self) -> std::ops::Range<u32> {
+        let buffers = if self.rule.is_one_dimensional() { 2 } else { 1 };
+        self.generation..self.generation + buffers
+    }
+
+    // Copy the new row of a one dimensional rule's current generation into the other buffers,
+    // which the next step writes its row into
+    fn encode_history_row_copy(&self, encoder: &mut wgpu::CommandEncoder) {
+        let size = self.width as u64 * std::mem::size_of::<u32>() as u64;
+        let offset = self.history_buffers.row(self.generation) as u64 * size;
+        let (current, other) = (self.generation, self.generation + 1);
+        for (source, destination) in [
+            (
+                self.cell_state_buffer(current),
+                self.cell_state_buffer(other),
+            ),
+            (self.cell_age_buffer(current), self.cell_age_buffer(other)),
+        ] {
+            encoder.copy_buffer_to_buffer(source, offset, destination, offset, size);
+        }
+    }
+
+    // Copy the whole of the current generation into the other buffers, e.g. when a one
+    // dimensional rule takes over a grid, so that they hold its history too
+    fn copy_current_generation(&self) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("History Encoder"),
+            });
+        let size = self.num_cells() as u64 * std::mem::size_of::<u32>() as u64;
+        let (current, other) = (self.generation, self.generation + 1);
+        for (source, destination) in [
+            (
+                self.cell_state_buffer(current),
+                self.cell_state_buffer(other),
+            ),
+            (self.cell_age_buffer(current), self.cell_age_buffer(other)),
+        ] {
+            encoder.copy_buffer_to_buffer(source, 0, destination, 0, size);
+        }
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    // The buffer holding the values of the current generation's cells, for continuous rules
+    fn current_cell_value_buffer(&self) -> &wgpu::Buffer {
+        if self.generation.is_multiple_of(2) {
+            &self.cell_state_buffers.value_a
+        } else {
+            &self.cell_state_buffers.value_b
+        }
+    }
+
+    // Copy the current generation back from the GPU, as row-major cell states, one layer after
+    // another. This blocks until the copy is complete, so it isn't available on the web.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_cells(&self) -> Vec<u32> {
+        self.read_buffer(self.current_cell_state_buffer())
+    }
+
+    // Copy the values of the current generation's cells back from the GPU, in row-major order.
+    // Only continuous rules have values, so this is empty for other rules.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_values(&self) -> Vec<f32> {
+        if !self.rule.is_continuous() {
+            return Vec::new();
+        }
+        self.read_buffer(self.current_cell_value_buffer())
+    }
+
+    // Copy a buffer with an element per cell back from the GPU, blocking until it arrives
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read_buffer<T: bytemuck::Pod>(&self, buffer: &wgpu::Buffer) -> Vec<T> {
+        let size = self.num_cells() as u64 * std::mem::size_of::<T>() as u64;
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cell State Readback Buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+        encoder.copy_buffer_to_buffer(buffer, 0, &readback_buffer, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let buffer_slice = readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).unwrap();
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .unwrap()
+            .expect("Failed to map cell state readback buffer");
+
+        let cells = bytemuck::cast_slice(&buffer_slice.get_mapped_range()).to_vec();
+        readback_buffer.unmap();
+        cells
+    }
+
+    // Replace the current generation with row-major cell states, one layer after another
+    pub fn write_cells(&mut self, cells: &[u32]) {
+        assert_eq!(
+            cells.len(),
+            self.num_cells() as usize,
+            "cell count doesn't match the {}x{}x{} grid",
+            self.width,
+            self.height,
+            self.depth
+        );
+        let ages = initial_ages(cells);
+        for generation in self.written_generations() {
+            self.queue.write_buffer(
+                self.cell_state_buffer(generation),
+                0,
+                bytemuck::cast_slice(cells),
+            );
+            self.queue.write_buffer(
+                self.cell_age_buffer(generation),
+                0,
+                bytemuck::cast_slice(&ages),
+            );
+        }
+        if self.rule.is_continuous() {
+            self.queue.write_buffer(
+                self.current_cell_value_buffer(),
+                0,
+                bytemuck::cast_slice(&initial_values(cells)),
+            );
+        }
+        self.count_population();
+    }
+
+    // Replace the values of the current generation's cells, in row-major order, for continuous
+    // rules. Their states should already have been written to match.
+    pub fn write_values(&mut self, values: &[f32]) {
+        assert!(
+            self.rule.is_continuous(),
+            "only continuous rules have cell values"
+        );
+        assert_eq!(
+            values.len(),
+            (self.width * self.height) as usize,
+            "value count doesn't match the {}x{} grid",
+            self.width,
+            self.height
+        );
+        self.queue.write_buffer(
+            self.current_cell_value_buffer(),
+            0,
+            bytemuck::cast_slice(values),
+        );
+    }
+
+    // The row of the cell state that's drawn in a row of the grid. One dimensional rules draw
+    // their rows as a history running down the grid, with the current generation at the
+    // bottom. Other rules draw each row where it is.
+    pub fn stored_row(&self, row: u32) -> u32 {
+        if !self.rule.is_one_dimensional() {
+            return row;
+        }
+        // The grid's rows count how many generations ago their generation was
+        (self.history_buffers.row(self.generation) + self.height - row) % self.height
+    }
+
+    // Set a single cell of the current generation
+    pub fn write_cell(&mut self, x: u32, y: u32, value: u32) {
+        let index = (y * self.width + x) as u64;
+        let offset = index * std::mem::size_of::<u32>() as u64;
+        for generation in self.written_generations() {
+            self.queue.write_buffer(
+                self.cell_state_buffer(generation),
+                offset,
+                bytemuck::cast_slice(&[value]),
+            );
+            self.queue.write_buffer(
+                self.cell_age_buffer(generation),
+                offset,
+                bytemuck::cast_slice(&initial_ages(&[value])),
+            );
+        }
+        if self.rule.is_continuous() {
+            self.queue.write_buffer(
+                self.current_cell_value_buffer(),
+                index * std::mem::size_of::<f32>() as u64,
+                bytemuck::cast_slice(&initial_values(&[value])),
+            );
+        }
+    }
+
+    pub fn randomise(&mut self) {
+        let init = InitialCellState::Random(self.rule);
+        self.reset(CellState::new(init, self.width, self.height, self.depth));
+    }
+
+    pub fn clear(&mut self) {
+        let init = InitialCellState::Empty;
+        self.reset(CellState::new(init, self.width, self.height, self.depth));
+    }
+
+    // Clear the grid and place a pattern in its centre
+    pub fn load_pattern(&mut self, pattern: &Pattern) {
+        let (width, height, depth) = (self.width, self.height, self.depth);
+        self.reset(CellState::from_pattern(
+            pattern, width, height, depth, self.rule,
+        ));
+    }
+
+    fn reset(&mut self, cell_state: CellState) {
+        // Reset the generation counter
+        self.generation = 0;
+        self.history_buffers.write(&self.queue, self.generation);
+
+        // Write the new cell states into the buffers
+        self.queue.write_buffer(
+            &self.cell_state_buffers.state_a,
+            0,
+            bytemuck::cast_slice(&cell_state.state_a),
+        );
+        self.queue.write_buffer(
+            &self.cell_state_buffers.state_b,
+            0,
+            bytemuck::cast_slice(&cell_state.state_b),
+        );
+
+        self.queue.write_buffer(
+            &self.cell_state_buffers.age_a,
+            0,
+            bytemuck::cast_slice(&initial_ages(&cell_state.state_a)),
+        );
+        self.queue.write_buffer(
+            &self.cell_state_buffers.age_b,
+            0,
+            bytemuck::cast_slice(&initial_ages(&cell_state.state_b)),
+        );
+
+        if self.rule.is_continuous() {
+            self.queue.write_buffer(
+                &self.cell_state_buffers.value_a,
+                0,
+                bytemuck::cast_slice(&cell_state.values),
+            );
+        }
+
+        self.population_counter.reset();
+        self.count_population();
+    }
+
+    // Change the grid dimensions, keeping the number of layers, and reallocate the cell state
+    // buffers and bind groups to match. The new grid is empty since the old cell state doesn't
+    // map onto it.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.resize_3d(width, height, self.depth);
+    }
+
+    // Change the grid dimensions, including the number of layers of a 3D grid. The new grid is
+    // empty.
+    pub fn resize_3d(&mut self, width: u32, height: u32, depth: u32) {
+        self.width = width;
+        self.height = height;
+        self.depth = depth;
+        self.generation = 0;
+
+        self.queue.write_buffer(
+            &self.grid_size_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[GridSizeUniform::new(width, height, depth)]),
+        );
+
+        let cell_state = CellState::new(InitialCellState::Empty, width, height, depth);
+        self.cell_state_buffers = CellStateBuffers::new(&self.device, &cell_state, self.rule);
+        self.history_buffers = HistoryBuffers::new(&self.device, height);
+        self.bind_groups = BindGroups::new(
+            &self.device,
+            &self.bind_group_layout,
+            &self.grid_size_uniform_buffer,
+            &self.rule_buffers,
+            &self.topology_uniform_buffer,
+            &self.history_buffers,
+            &self.cell_state_buffers,
+        );
+        let buffers = &self.cell_state_buffers;
+        self.population_counter.set_cell_state_buffers(
+            &self.device,
+            &self.queue,
+            [&buffers.state_a, &buffers.state_b],
+            [&buffers.value_a, &buffers.value_b],
+            &self.history_buffers.uniform,
+            [width, self.num_cells()],
+        );
+        self.count_population();
+    }
+}
@@ -0,0 +1,136 @@
+// How the cells are coloured. The settings are passed to fs_main in shader.wgsl as a uniform,
+// alongside the camera's view transform.
+
+use crate::palette::Palette;
+
+pub const DEFAULT_TRAIL_LENGTH: u32 = 16;
+pub const MAX_TRAIL_LENGTH: u32 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ColourMode {
+    // The lamp gradient, by cell position
+    Position,
+    // By how many generations the cell has been alive, so newborn cells, still lifes and
+    // oscillators stand apart
+    Age,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct StyleUniform {
+    colour_mode: u32,
+    trail_length: u32,
+    _padding: [u32; 2],
+    // The current palette's colours, padded to vec4s
+    alive: [f32; 4],
+    lamps: [[f32; 4]; 3],
+    age_gradient: [[f32; 4]; 4],
+    wireworld: [[f32; 4]; 3],
+    grid_line_colour: [f32; 4],
+}
+
+pub(crate) struct Style {
+    colour_mode: ColourMode,
+    // Dead cells fade out over this many generations while trails are shown
+    trail_length: u32,
+    trails: bool,
+    grid_lines: bool,
+    // The built-in palettes followed by any user palettes. There's always at least one.
+    palettes: Vec<Palette>,
+    palette_index: usize,
+}
+
+impl Style {
+    // Trails are shown from the start if a length is given.
+    // The user palettes are added after the built-in ones, and the first palette with the
+    // given name (ignoring case) is used to start with.
+    pub(crate) fn new(
+        trail_length: Option<u32>,
+        user_palettes: &[Palette],
+        palette_name: Option<&str>,
+    ) -> Self {
+        let mut palettes = Palette::built_in();
+        palettes.extend_from_slice(user_palettes);
+        let palette_index = palette_name
+            .and_then(|name| {
+                palettes
+                    .iter()
+                    .position(|palette| palette.name.eq_ignore_ascii_case(name))
+            })
+            .unwrap_or(0);
+
+        Self {
+            colour_mode: ColourMode::Position,
+            trail_length: trail_length
+                .unwrap_or(DEFAULT_TRAIL_LENGTH)
+                .clamp(1, MAX_TRAIL_LENGTH),
+            trails: trail_length.is_some(),
+            grid_lines: false,
+            palettes,
+            palette_index,
+        }
+    }
+
+    pub(crate) fn colour_mode(&self) -> ColourMode {
+        self.colour_mode
+    }
+
+    pub(crate) fn set_colour_mode(&mut self, colour_mode: ColourMode) {
+        self.colour_mode = colour_mode;
+    }
+
+    pub(crate) fn trails(&self) -> bool {
+        self.trails
+    }
+
+    pub(crate) fn set_trails(&mut self, trails: bool) {
+        self.trails = trails;
+    }
+
+    pub(crate) fn grid_lines(&self) -> bool {
+        self.grid_lines
+    }
+
+    pub(crate) fn set_grid_lines(&mut self, grid_lines: bool) {
+        self.grid_lines = grid_lines;
+    }
+
+    pub(crate) fn palette(&self) -> &Palette {
+        &self.palettes[self.palette_index]
+    }
+
+    // Switch to the next palette, wrapping around after the last
+    pub(crate) fn next_palette(&mut self) {
+        self.palette_index = (self.palette_index + 1) % self.palettes.len();
+    }
+
+    pub(crate) fn uniform(&self) -> StyleUniform {
+        let palette = self.palette();
+        let vec4 = |[r, g, b]: [f32; 3]| [r, g, b, 1.0];
+
+        StyleUniform {
+            // Must match the COLOUR_MODE_ constants in shader.wgsl
+            colour_mode: match self.colour_mode {
+                ColourMode::Position => 0,
+                ColourMode::Age => 1,
+            },
+            trail_length: if self.trails { self.trail_length } else { 0 },
+            _padding: [0; 2],
+            alive: vec4(palette.alive),
+            lamps: palette.lamps.map(vec4),
+            age_gradient: palette.age_gradient.map(vec4),
+            wireworld: palette.wireworld.map(vec4),
+            grid_line_colour: grid_line_colour(palette.background),
+        }
+    }
+}
+
+// Faint dark lines on light backgrounds, and faint light lines on dark backgrounds
+fn grid_line_colour([r, g, b, _]: [f32; 4]) -> [f32; 4] {
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    if luminance > 0.5 {
+        [0.0, 0.0, 0.0, 0.3]
+    } else {
+        [1.0, 1.0, 1.0, 0.25]
+    }
+}
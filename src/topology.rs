@@ -0,0 +1,246 @@
+// How the edges of the grid join up, which decides the neighbours of cells along the edges.
+// The neighbour lookup is implemented by neighbour_active in compute.wgsl and mirrored by
+// CpuSimulation::neighbour_active.
+// The layers of a 3D grid each join up as a 2D grid would, and the stack of layers wraps around
+// from top to bottom unless the grid is bounded.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Topology {
+    // A finite plane, surrounded by dead cells
+    Bounded,
+    // Both axes wrap around
+    #[default]
+    Torus,
+    // The x axis wraps around, and crossing the top or bottom edge mirrors x
+    KleinBottle,
+    // Crossing the left or right edge mirrors y, and crossing the top or bottom edge mirrors x
+    // (the real projective plane)
+    CrossSurface,
+    // The x axis wraps around, and the top and bottom edges are surrounded by dead cells
+    Cylinder,
+}
+
+impl Topology {
+    pub const ALL: [Topology; 5] = [
+        Topology::Bounded,
+        Topology::Torus,
+        Topology::KleinBottle,
+        Topology::CrossSurface,
+        Topology::Cylinder,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Topology::Bounded => "bounded",
+            Topology::Torus => "torus",
+            Topology::KleinBottle => "klein",
+            Topology::CrossSurface => "cross",
+            Topology::Cylinder => "cylinder",
+        }
+    }
+
+    // The next topology in ALL, wrapping around after the last
+    pub fn next(self) -> Self {
+        let i = Self::ALL
+            .iter()
+            .position(|&topology| topology == self)
+            .unwrap();
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    // Map a neighbour's coordinates, which may be outside the grid, back onto the grid.
+    // Returns None if the neighbour is outside a bounded edge.
+    pub(crate) fn wrap(self, x: i64, y: i64, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (w, h) = (width as i64, height as i64);
+        let (mut x, mut y) = (x, y);
+        // How many times the grid's edges are crossed to get to the neighbour. Large
+        // neighbourhoods can reach more than a whole grid away.
+        let wraps_x = x.div_euclid(w);
+        let wraps_y = y.div_euclid(h);
+
+        match self {
+            Topology::Bounded if wraps_x != 0 || wraps_y != 0 => return None,
+            Topology::Cylinder if wraps_y != 0 => return None,
+            Topology::KleinBottle if wraps_y % 2 != 0 => x = w - 1 - x,
+            Topology::CrossSurface => {
+                if wraps_x % 2 != 0 {
+                    y = h - 1 - y;
+                }
+                if wraps_y % 2 != 0 {
+                    x = w - 1 - x;
+                }
+            }
+            _ => {}
+        }
+
+        Some((x.rem_euclid(w) as u32, y.rem_euclid(h) as u32))
+    }
+
+    // The same for the layer of a neighbour in a 3D grid
+    pub(crate) fn wrap_layer(self, z: i64, depth: u32) -> Option<u32> {
+        let d = depth as i64;
+        if self == Topology::Bounded && !(0..d).contains(&z) {
+            return None;
+        }
+        Some(z.rem_euclid(d) as u32)
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopologyParseError(String);
+
+impl fmt::Display for TopologyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = Topology::ALL
+            .iter()
+            .map(|topology| topology.name())
+            .collect();
+        write!(
+            f,
+            "unknown topology '{}', expected one of {}",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for TopologyParseError {}
+
+impl FromStr for Topology {
+    type Err = TopologyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|topology| topology.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| TopologyParseError(s.to_string()))
+    }
+}
+
+// Must match the TOPOLOGY_ constants in compute.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct TopologyUniform {
+    topology: u32,
+    _padding: [u32; 3],
+}
+
+impl From<Topology> for TopologyUniform {
+    fn from(topology: Topology) -> Self {
+        Self {
+            topology: match topology {
+                Topology::Bounded => 0,
+                Topology::Torus => 1,
+                Topology::KleinBottle => 2,
+                Topology::CrossSurface => 3,
+                Topology::Cylinder => 4,
+            },
+            _padding: [0; 3],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Neighbours just past each edge and two opposite corners of a 5x4 grid, followed by where
+    // they should land. The grid isn't square so that mixing up x and y shows.
+    const OUTSIDE: [(i64, i64); 6] = [(-1, 1), (5, 1), (1, -1), (1, 4), (-1, -1), (5, 4)];
+
+    fn assert_wraps(topology: Topology, expected: [Option<(u32, u32)>; 6]) {
+        for (&(x, y), expected) in OUTSIDE.iter().zip(expected) {
+            assert_eq!(topology.wrap(x, y, 5, 4), expected, "{topology} ({x}, {y})");
+        }
+        // Cells inside the grid are never moved
+        for (x, y) in (0..4).flat_map(|y| (0..5).map(move |x| (x, y))) {
+            assert_eq!(topology.wrap(x as i64, y as i64, 5, 4), Some((x, y)));
+        }
+    }
+
+    #[test]
+    fn bounded_edges_have_no_neighbours() {
+        assert_wraps(Topology::Bounded, [None; 6]);
+    }
+
+    #[test]
+    fn torus_edges_join_their_opposite_edges() {
+        assert_wraps(
+            Topology::Torus,
+            [
+                Some((4, 1)),
+                Some((0, 1)),
+                Some((1, 3)),
+                Some((1, 0)),
+                Some((4, 3)),
+                Some((0, 0)),
+            ],
+        );
+    }
+
+    #[test]
+    fn klein_bottle_mirrors_x_across_the_top_and_bottom() {
+        assert_wraps(
+            Topology::KleinBottle,
+            [
+                Some((4, 1)),
+                Some((0, 1)),
+                Some((3, 3)),
+                Some((3, 0)),
+                Some((0, 3)),
+                Some((4, 0)),
+            ],
+        );
+    }
+
+    #[test]
+    fn cross_surface_mirrors_across_every_edge() {
+        // Crossing both edges at a corner mirrors both axes, which leads back to the same corner
+        assert_wraps(
+            Topology::CrossSurface,
+            [
+                Some((4, 2)),
+                Some((0, 2)),
+                Some((3, 3)),
+                Some((3, 0)),
+                Some((0, 0)),
+                Some((4, 3)),
+            ],
+        );
+    }
+
+    #[test]
+    fn cylinder_only_wraps_x() {
+        assert_wraps(
+            Topology::Cylinder,
+            [Some((4, 1)), Some((0, 1)), None, None, None, None],
+        );
+    }
+
+    #[test]
+    fn neighbours_more_than_a_grid_away_wrap_more_than_once() {
+        // Two crossings of the top edge mirror x twice, which cancels out
+        assert_eq!(Topology::KleinBottle.wrap(1, 9, 5, 4), Some((1, 1)));
+        assert_eq!(Topology::KleinBottle.wrap(1, 5, 5, 4), Some((3, 1)));
+        assert_eq!(Topology::CrossSurface.wrap(-6, 1, 5, 4), Some((4, 1)));
+        assert_eq!(Topology::CrossSurface.wrap(-1, 1, 5, 4), Some((4, 2)));
+    }
+
+    #[test]
+    fn layers_wrap_unless_bounded() {
+        assert_eq!(Topology::Bounded.wrap_layer(-1, 3), None);
+        assert_eq!(Topology::Bounded.wrap_layer(3, 3), None);
+        assert_eq!(Topology::Bounded.wrap_layer(2, 3), Some(2));
+        assert_eq!(Topology::Torus.wrap_layer(-1, 3), Some(2));
+        assert_eq!(Topology::Torus.wrap_layer(3, 3), Some(0));
+    }
+}